@@ -1,27 +1,58 @@
-use crate::shared::context::Context;
-use crate::shared::resources::common::{self, ControllerResource, MonitorResource};
-use crate::shared::settings::Settings;
+use crate::shared::auth;
+use crate::shared::context::{Context, DispatchFailures};
+use crate::shared::metrics::{METRICS, monitor_labels};
+use crate::shared::resources::common::{
+    self, ControllerResource, LocationStatus, MonitorResource, MonitorState,
+};
+use crate::shared::resources::worker::{self, ProbeResult};
+use crate::shared::settings::LocationSettings;
 use futures::StreamExt;
 use kube::{
-    Api, Client, ResourceExt,
+    Api, ResourceExt,
+    api::{Patch, PatchParams},
     runtime::{Controller, controller::Action},
 };
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::Instant;
+use tracing::{error, info, warn};
 use chrono::Utc;
 
+const DEFAULT_RETRY_AFTER: u64 = 5;
+const DISPATCH_BACKOFF_BASE: u64 = 5;
+const DISPATCH_BACKOFF_MAX: u64 = 300;
+/// How long monitors wait for the worker replicas to be listed
+const SHARDS_SYNC_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Failed to reconcile: {0}")]
     Anyhow(#[from] anyhow::Error),
+    #[error("Failed to dispatch to worker: {0}")]
+    Dispatch(String),
 }
 
 pub async fn reconcile<T>(obj: Arc<T>, ctx: Arc<Context>) -> Result<Action, Error>
 where
-    T: MonitorResource + serde::Serialize + std::fmt::Debug,
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
 {
     // Validate the resource
     obj.validate().map_err(Error::Anyhow)?;
+    if ctx.settings.controller.probe_only.unwrap_or(false) && obj.reads_secrets() {
+        return Err(Error::Anyhow(anyhow::anyhow!(
+            "{} {} reads Secrets or ConfigMaps, which workers in probe-only mode cannot",
+            T::kind(&()),
+            obj.name_any()
+        )));
+    }
+    if let Some(flap_detection) = &obj.monitor_config().flap_detection {
+        flap_detection.validate().map_err(Error::Anyhow)?;
+    }
+    if let Some(locations) = &ctx.settings.controller.locations {
+        let names: Vec<&str> = locations.iter().map(|l| l.name.as_str()).collect();
+        obj.monitor_config()
+            .validate_locations(&names)
+            .map_err(Error::Anyhow)?;
+    }
 
     // Check if we need to reconcile based on timing
     // This prevents tight loops when the worker updates the status
@@ -48,8 +79,140 @@ where
         }
     }
 
-    let client = reqwest::Client::new();
-    let worker_url = common::build_worker_url::<T>(&ctx.settings.controller.base_url);
+    // Status changes also trigger a reconcile, do not let them cut the dispatch backoff short
+    let key = dispatch_key::<T>(&*obj);
+    let retry_at = ctx
+        .dispatch
+        .failures
+        .lock()
+        .unwrap()
+        .get(&key)
+        .map(|failures| failures.retry_at);
+    if let Some(wait) = retry_at.and_then(|at| at.checked_duration_since(Instant::now())) {
+        info!(
+            "Backing off dispatch of {}, requeueing in {:?}",
+            obj.name_any(),
+            wait
+        );
+        return Ok(Action::requeue(wait));
+    }
+
+    let body = serde_json::to_vec(&*obj).map_err(|e| Error::Anyhow(e.into()))?;
+    let outcome = match &ctx.settings.controller.locations {
+        Some(locations) => dispatch_to_locations(&*obj, &ctx, locations, &body).await?,
+        None => dispatch_to_worker(&*obj, &ctx, &body).await,
+    };
+
+    match outcome {
+        Outcome::Done => {
+            ctx.dispatch.failures.lock().unwrap().remove(&key);
+            Ok(obj.success_policy())
+        }
+        Outcome::Busy(delay) => {
+            // The worker is saturated, back off instead of waiting a full polling interval
+            warn!("Worker busy, requeueing {} in {:?}", obj.name_any(), delay);
+            Ok(Action::requeue(delay))
+        }
+        Outcome::Failed(message) => {
+            error!(
+                "Failed to dispatch {} to worker: {}",
+                obj.name_any(),
+                message
+            );
+            mark_worker_unreachable(&*obj, &ctx, &message).await;
+            Err(Error::Dispatch(message))
+        }
+    }
+}
+
+/// The outcome of dispatching a monitor to its worker(s)
+enum Outcome {
+    /// The check was accepted, or its result recorded
+    Done,
+    /// The worker is saturated and asked to retry after the delay
+    Busy(std::time::Duration),
+    /// The worker could not be reached or failed
+    Failed(String),
+}
+
+/// A worker's answer to a dispatched check
+enum Sent {
+    Accepted(reqwest::Response),
+    Busy(std::time::Duration),
+}
+
+/// Posts the signed monitor to a worker endpoint, recording how long the worker took to answer
+async fn send(ctx: &Context, kind: &str, url: &str, body: &[u8]) -> Result<Sent, String> {
+    let started = std::time::Instant::now();
+    let result = send_request(ctx, url, body).await;
+    METRICS
+        .dispatch_duration
+        .observe(vec![("kind", kind.to_string())], started.elapsed());
+    result
+}
+
+async fn send_request(ctx: &Context, url: &str, body: &[u8]) -> Result<Sent, String> {
+    let mut request = ctx
+        .dispatch
+        .http_client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(key) = &ctx.dispatch.signing_key {
+        let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let signature = key
+            .sign("POST", &path, body, Utc::now().timestamp())
+            .map_err(|e| e.to_string())?;
+        request = request.header(auth::SIGNATURE_HEADER, signature);
+    }
+
+    let response = request
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(Sent::Accepted(response))
+    } else if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE
+    {
+        Ok(Sent::Busy(retry_after(&response)))
+    } else {
+        Err(format!("worker returned {}", response.status()))
+    }
+}
+
+async fn read_probe(response: reqwest::Response) -> Result<ProbeResult, String> {
+    response
+        .json::<ProbeResult>()
+        .await
+        .map_err(|e| format!("invalid probe result from worker: {}", e))
+}
+
+/// Dispatches the monitor to the single worker at base_url.
+/// In probe-only mode the worker returns the result and the controller records it.
+async fn dispatch_to_worker<T>(obj: &T, ctx: &Context, body: &[u8]) -> Outcome
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    // With sharding, each monitor sticks to the replica it hashes to
+    let base_url = match &ctx.dispatch.shards {
+        Some(shards) => match shards.worker_for(&dispatch_key(obj)) {
+            Some(base_url) => base_url,
+            // The replicas are not listed yet right after startup, wait for them instead of failing
+            None if !shards.is_synced() => return Outcome::Busy(SHARDS_SYNC_DELAY),
+            None => return Outcome::Failed("no ready worker replicas".to_string()),
+        },
+        None => ctx.settings.controller.base_url.clone(),
+    };
+    let probe_only = ctx.settings.controller.probe_only.unwrap_or(false);
+    let mut worker_url = common::build_worker_url::<T>(&base_url);
+    if probe_only {
+        worker_url.push_str("/probe");
+    }
 
     info!(
         "Dispatching {} {} to worker at {}",
@@ -58,56 +221,271 @@ where
         worker_url
     );
 
-    let res = client.post(&worker_url).json(&*obj).send().await;
-
-    match res {
-        Ok(response) => {
-            if response.status().is_success() {
-                info!("Successfully dispatched to worker");
-            } else {
-                error!("Worker returned error: {:?}", response.status());
+    match send(ctx, &T::kind(&()), &worker_url, body).await {
+        Ok(Sent::Accepted(response)) if probe_only => match read_probe(response).await {
+            Ok(probe_result) => {
+                worker::record_result(obj, ctx.client.clone(), probe_result, None).await;
+                Outcome::Done
             }
+            Err(message) => Outcome::Failed(message),
+        },
+        Ok(Sent::Accepted(_)) => {
+            info!("Successfully dispatched to worker");
+            Outcome::Done
         }
-        Err(e) => {
-            error!("Failed to dispatch to worker: {:?}", e);
+        Ok(Sent::Busy(delay)) => Outcome::Busy(delay),
+        Err(message) => Outcome::Failed(message),
+    }
+}
+
+/// Probes the monitor from each of its selected locations and records the combined result
+async fn dispatch_to_locations<T>(
+    obj: &T,
+    ctx: &Context,
+    locations: &[LocationSettings],
+    body: &[u8],
+) -> Result<Outcome, Error>
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let config = obj.monitor_config();
+    let selected: Vec<&LocationSettings> = match &config.locations {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                locations
+                    .iter()
+                    .find(|l| &l.name == name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown location: {}", name))
+            })
+            .collect::<anyhow::Result<_>>()?,
+        None => locations.iter().collect(),
+    };
+
+    info!(
+        "Dispatching {} {} to {} locations",
+        T::kind(&()),
+        obj.name_any(),
+        selected.len()
+    );
+
+    let responses = futures::future::join_all(selected.iter().map(|location| async move {
+        let url = format!(
+            "{}/probe",
+            common::build_worker_url::<T>(&location.base_url)
+        );
+        let result = match send(ctx, &T::kind(&()), &url, body).await {
+            Ok(Sent::Accepted(response)) => read_probe(response).await.map_err(|m| (m, None)),
+            Ok(Sent::Busy(delay)) => Err(("worker busy".to_string(), Some(delay))),
+            Err(message) => Err((message, None)),
+        };
+        (location.name.clone(), result)
+    }))
+    .await;
+
+    let mut results = Vec::new();
+    let mut statuses = Vec::new();
+    let mut failures = Vec::new();
+    let mut busy = None;
+    for (name, result) in responses {
+        match result {
+            Ok(probe_result) => {
+                statuses.push(LocationStatus {
+                    name: name.clone(),
+                    state: probe_result.result.state.clone(),
+                    message: probe_result.result.message.clone(),
+                    response_time_ms: probe_result.result.response_time_ms,
+                });
+                results.push((name, probe_result));
+            }
+            Err((message, delay)) => {
+                warn!(
+                    "Location {} failed for {}: {}",
+                    name,
+                    obj.name_any(),
+                    message
+                );
+                busy = busy.max(delay);
+                statuses.push(LocationStatus {
+                    name: name.clone(),
+                    state: MonitorState::Error,
+                    message: Some(message.clone()),
+                    response_time_ms: None,
+                });
+                failures.push(format!("{}: {}", name, message));
+            }
         }
     }
 
-    Ok(obj.success_policy())
+    if results.is_empty() {
+        return Ok(match busy {
+            Some(delay) => Outcome::Busy(delay),
+            None => Outcome::Failed(failures.join("; ")),
+        });
+    }
+
+    let combined = worker::aggregate_locations(&results, config.location_quorum);
+    worker::record_result(obj, ctx.client.clone(), combined, Some(statuses)).await;
+    Ok(Outcome::Done)
+}
+
+fn dispatch_key<T>(obj: &T) -> String
+where
+    T: ControllerResource,
+{
+    monitor_key::<T>(&obj.namespace().unwrap_or_default(), &obj.name_any())
+}
+
+fn monitor_key<T>(namespace: &str, name: &str) -> String
+where
+    T: ControllerResource,
+{
+    format!("{}/{}/{}", T::kind(&()), namespace, name)
+}
+
+/// Sets the WorkerUnreachable condition and emits a Warning event when the monitor first becomes unreachable
+async fn mark_worker_unreachable<T>(obj: &T, ctx: &Context, message: &str)
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let mut conditions = obj
+        .status()
+        .and_then(|s| s.conditions.clone())
+        .unwrap_or_default();
+    let already_unreachable = conditions
+        .iter()
+        .any(|c| c.type_ == common::WORKER_UNREACHABLE_CONDITION && c.status == "True");
+    if already_unreachable {
+        return;
+    }
+
+    let name = obj.name_any();
+    let ns = obj.namespace().unwrap_or_else(|| "default".to_string());
+    common::set_condition(
+        &mut conditions,
+        common::WORKER_UNREACHABLE_CONDITION,
+        "True",
+        "DispatchFailed",
+        Some(message),
+        Utc::now(),
+    );
+
+    let api: Api<T> = Api::namespaced(ctx.client.clone(), &ns);
+    let patch = serde_json::json!({ "status": { "conditions": conditions } });
+    if let Err(e) = api
+        .patch_status(&name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        error!("Failed to set WorkerUnreachable on {}: {:?}", name, e);
+    }
+
+    if let Err(e) = common::publish_event(
+        ctx.client.clone(),
+        &name,
+        &T::kind(&()),
+        &T::api_version(&()),
+        &ns,
+        "WorkerUnreachable",
+        &format!("Could not dispatch check to worker: {}", message),
+        "Warning",
+    )
+    .await
+    {
+        error!("Failed to publish event for {}: {:?}", name, e);
+    }
+}
+
+/// Delay before retrying a failed dispatch, doubling with each consecutive failure
+fn dispatch_backoff(failures: u32) -> std::time::Duration {
+    let seconds = DISPATCH_BACKOFF_BASE.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    std::time::Duration::from_secs(seconds.min(DISPATCH_BACKOFF_MAX))
+}
+
+/// Reads the Retry-After header of a rejected dispatch, defaulting to a few seconds
+fn retry_after(response: &reqwest::Response) -> std::time::Duration {
+    let seconds = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RETRY_AFTER);
+    std::time::Duration::from_secs(seconds)
 }
 
 pub fn error_policy<T>(obj: Arc<T>, error: &Error, ctx: Arc<Context>) -> Action
 where
     T: ControllerResource,
 {
+    let reason = match error {
+        Error::Anyhow(_) => "invalid",
+        Error::Dispatch(_) => "dispatch",
+    };
+    METRICS.reconcile_errors.inc(vec![
+        ("kind", T::kind(&()).to_string()),
+        ("reason", reason.to_string()),
+    ]);
+
     match error {
         Error::Anyhow(e) => obj.error_policy(e, ctx),
+        Error::Dispatch(_) => {
+            let key = dispatch_key(&*obj);
+            let mut failures = ctx.dispatch.failures.lock().unwrap();
+            let count = failures.get(&key).map_or(1, |f| f.count + 1);
+            let backoff = dispatch_backoff(count);
+            failures.insert(
+                key,
+                DispatchFailures {
+                    count,
+                    retry_at: Instant::now() + backoff,
+                },
+            );
+            Action::requeue(backoff)
+        }
     }
 }
 
-pub fn run_monitor_controller<T>(
-    client: Client,
-    settings: Settings,
-) -> impl futures::Future<Output = ()>
+pub fn run_monitor_controller<T>(context: Arc<Context>) -> impl futures::Future<Output = ()>
 where
     T: MonitorResource + serde::Serialize + std::fmt::Debug + serde::de::DeserializeOwned,
 {
-    let monitors = Api::<T>::all(client.clone());
-    let context = Arc::new(Context {
-        client: client.clone(),
-        settings: settings.clone(),
-    });
+    let monitors = Api::<T>::all(context.client.clone());
+    let ctx = context.clone();
 
     Controller::new(monitors, Default::default())
         .run(reconcile, error_policy, context)
-        .for_each(|res| async move {
-            match res {
-                Ok(o) => info!("reconciled {:?}", o),
-                Err(e) => info!("reconcile failed: {:?}", e),
+        .for_each(move |res| {
+            let ctx = ctx.clone();
+            async move {
+                match res {
+                    Ok(o) => info!("reconciled {:?}", o),
+                    // Every monitor is requeued, so a deleted one ends up here once its requeue fires
+                    Err(kube::runtime::controller::Error::ObjectNotFound(obj_ref)) => {
+                        forget_monitor::<T>(
+                            &ctx,
+                            obj_ref.namespace.as_deref().unwrap_or_default(),
+                            &obj_ref.name,
+                        );
+                    }
+                    Err(e) => info!("reconcile failed: {:?}", e),
+                }
             }
         })
 }
 
+/// Drops what the controller tracks for a monitor that no longer exists
+pub fn forget_monitor<T>(ctx: &Context, namespace: &str, name: &str)
+where
+    T: ControllerResource,
+{
+    info!("{} {}/{} was deleted", T::kind(&()), namespace, name);
+    ctx.dispatch
+        .failures
+        .lock()
+        .unwrap()
+        .remove(&monitor_key::<T>(namespace, name));
+    METRICS.forget_monitor(&monitor_labels(&T::kind(&()), namespace, name));
+}
+
 pub async fn reconcile_notifier<T>(obj: Arc<T>, _ctx: Arc<Context>) -> Result<Action, Error>
 where
     T: ControllerResource + serde::Serialize + std::fmt::Debug,
@@ -119,18 +497,11 @@ where
     Ok(obj.success_policy())
 }
 
-pub fn run_notifier_controller<T>(
-    client: Client,
-    settings: Settings,
-) -> impl futures::Future<Output = ()>
+pub fn run_notifier_controller<T>(context: Arc<Context>) -> impl futures::Future<Output = ()>
 where
     T: ControllerResource + serde::Serialize + std::fmt::Debug + serde::de::DeserializeOwned,
 {
-    let notifiers = Api::<T>::all(client.clone());
-    let context = Arc::new(Context {
-        client: client.clone(),
-        settings: settings.clone(),
-    });
+    let notifiers = Api::<T>::all(context.client.clone());
 
     Controller::new(notifiers, Default::default())
         .run(reconcile_notifier, error_policy, context)
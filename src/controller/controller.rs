@@ -1,21 +1,110 @@
-use crate::controller::common;
-use crate::shared::resources::monitors::http_monitor::v1alpha1::HTTPMonitor;
+use crate::controller::leader::LeaderElector;
+use crate::controller::{common, server, sharding};
+use crate::shared::context::{Context, WorkerDispatch};
+use crate::shared::resources::monitors::dns_monitor::v1alpha1::DNSMonitor;
+use crate::shared::resources::monitors::http_monitor::v1alpha2::HTTPMonitor;
 use crate::shared::resources::monitors::tcp_monitor::v1alpha1::TCPMonitor;
+use crate::shared::resources::monitors::tls_certificate_monitor::v1alpha1::TLSCertificateMonitor;
 use crate::shared::resources::notifiers::discord_notifier::v1alpha1::DiscordNotifier;
+use crate::shared::resources::notifiers::slack_notifier::v1alpha1::SlackNotifier;
+use crate::shared::resources::notifiers::webhook_notifier::v1alpha1::WebhookNotifier;
 use kube::Client;
-use tracing::info;
+use std::sync::Arc;
+use tracing::{error, info};
 
 use crate::shared::settings::Settings;
 
 pub async fn run(client: Client, settings: Settings) -> anyhow::Result<()> {
-    info!("Starting TCPMonitor and HTTPMonitor controllers");
+    info!("Starting TCPMonitor, HTTPMonitor, DNSMonitor and TLSCertificateMonitor controllers");
 
-    let tcp_fut = common::run_monitor_controller::<TCPMonitor>(client.clone(), settings.clone());
-    let http_fut = common::run_monitor_controller::<HTTPMonitor>(client.clone(), settings.clone());
-    let discord_fut =
-        common::run_notifier_controller::<DiscordNotifier>(client.clone(), settings.clone());
+    let dispatch = WorkerDispatch::new(client.clone(), &settings).await?;
+    let context = Arc::new(Context {
+        client,
+        settings,
+        dispatch,
+    });
 
-    futures::future::join3(tcp_fut, http_fut, discord_fut).await;
+    if let (Some(service), Some(shards)) = (
+        &context.settings.controller.worker_service,
+        &context.dispatch.shards,
+    ) {
+        tokio::spawn(sharding::watch_workers(
+            context.client.clone(),
+            service.clone(),
+            shards.clone(),
+        ));
+    }
+
+    let leader = context
+        .settings
+        .controller
+        .leader_election
+        .as_ref()
+        .map(|le| LeaderElector::new(context.client.clone(), le));
+
+    if let Some(port) = context.settings.controller.probe_port {
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+        let leader_flag = leader.as_ref().map(|l| l.leader_flag());
+        tokio::spawn(async move {
+            if let Err(e) = server::run(listener, leader_flag).await {
+                error!("Controller probe server failed: {:?}", e);
+            }
+        });
+    }
+
+    let controllers = async {
+        let tcp_fut = common::run_monitor_controller::<TCPMonitor>(context.clone());
+        let http_fut = common::run_monitor_controller::<HTTPMonitor>(context.clone());
+        let dns_fut = common::run_monitor_controller::<DNSMonitor>(context.clone());
+        let tls_fut = common::run_monitor_controller::<TLSCertificateMonitor>(context.clone());
+        let discord_fut = common::run_notifier_controller::<DiscordNotifier>(context.clone());
+        let slack_fut = common::run_notifier_controller::<SlackNotifier>(context.clone());
+        let webhook_fut = common::run_notifier_controller::<WebhookNotifier>(context.clone());
+
+        tokio::join!(
+            tcp_fut,
+            http_fut,
+            dns_fut,
+            tls_fut,
+            discord_fut,
+            slack_fut,
+            webhook_fut
+        );
+    };
+
+    let Some(leader) = leader else {
+        controllers.await;
+        return Ok(());
+    };
+
+    // Standby replicas wait here until the leader goes away
+    leader.acquire().await;
+    tokio::select! {
+        _ = controllers => {}
+        _ = leader.hold() => {
+            // Exit so the replica restarts as a standby instead of dispatching alongside the new leader
+            return Err(anyhow::anyhow!("Lost leadership"));
+        }
+        _ = shutdown_signal() => {
+            info!("Shutting down, handing over leadership");
+            leader.release().await;
+        }
+    }
 
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let mut terminate =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {:?}", e);
+                return std::future::pending().await;
+            }
+        };
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
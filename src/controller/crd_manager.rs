@@ -1,4 +1,6 @@
-use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceDefinition, CustomResourceDefinitionVersion,
+};
 use kube::{
     Client, CustomResourceExt,
     api::{Api, PostParams},
@@ -9,7 +11,11 @@ pub async fn init_crds<T>(client: Client) -> anyhow::Result<()>
 where
     T: CustomResourceExt,
 {
-    let crd = T::crd();
+    init_crd(client, T::crd()).await
+}
+
+/// Creates or updates a CRD, which may serve several versions
+pub async fn init_crd(client: Client, crd: CustomResourceDefinition) -> anyhow::Result<()> {
     let name = crd.metadata.name.as_ref().unwrap();
     let api: Api<CustomResourceDefinition> = Api::all(client);
 
@@ -26,7 +32,11 @@ where
         for existing_version in existing_spec.versions {
             if !versions.iter().any(|v| v.name == existing_version.name) {
                 warn!("Preserving old version: {}", existing_version.name);
-                versions.push(existing_version);
+                // Only one version can be stored, and that is the one the new CRD picked
+                versions.push(CustomResourceDefinitionVersion {
+                    storage: false,
+                    ..existing_version
+                });
             }
         }
         new_crd.spec.versions = versions;
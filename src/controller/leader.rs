@@ -0,0 +1,216 @@
+use crate::shared::settings::LeaderElectionSettings;
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use kube::{
+    Api, Client,
+    api::{ObjectMeta, PostParams},
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+const DEFAULT_LEASE_NAME: &str = "kastlewatch-controller";
+const DEFAULT_LEASE_DURATION: u64 = 15;
+
+/// Whether the lease has run out, so another replica may take it over
+pub fn lease_expired(spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+    match (
+        &spec.holder_identity,
+        &spec.renew_time,
+        spec.lease_duration_seconds,
+    ) {
+        (Some(_), Some(renew_time), Some(duration)) => {
+            renew_time.0 + chrono::Duration::seconds(duration as i64) < now
+        }
+        _ => true,
+    }
+}
+
+/// Lease-based leader election, so only one controller replica dispatches checks
+pub struct LeaderElector {
+    api: Api<Lease>,
+    lease_name: String,
+    identity: String,
+    lease_duration: Duration,
+    renew_period: Duration,
+    leader: Arc<AtomicBool>,
+}
+
+impl LeaderElector {
+    pub fn new(client: Client, settings: &LeaderElectionSettings) -> Self {
+        let lease_duration = settings.lease_duration.unwrap_or(DEFAULT_LEASE_DURATION);
+        // The pod name identifies the replica, falling back to the hostname outside a pod
+        let identity = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| format!("kastlewatch-controller-{}", std::process::id()));
+
+        LeaderElector {
+            api: Api::namespaced(client, &settings.namespace),
+            lease_name: settings
+                .lease_name
+                .clone()
+                .unwrap_or_else(|| DEFAULT_LEASE_NAME.to_string()),
+            identity,
+            lease_duration: Duration::from_secs(lease_duration),
+            renew_period: Duration::from_secs(
+                settings.renew_period.unwrap_or((lease_duration / 3).max(1)),
+            ),
+            leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag set while this replica holds the lease
+    pub fn leader_flag(&self) -> Arc<AtomicBool> {
+        self.leader.clone()
+    }
+
+    fn spec(&self, acquire_time: DateTime<Utc>, now: DateTime<Utc>, transitions: i32) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+            acquire_time: Some(MicroTime(acquire_time)),
+            renew_time: Some(MicroTime(now)),
+            lease_transitions: Some(transitions),
+        }
+    }
+
+    /// Tries once to acquire or renew the lease, returning whether this replica holds it
+    pub async fn try_acquire(&self) -> anyhow::Result<bool> {
+        let now = Utc::now();
+        let result = match self.api.get_opt(&self.lease_name).await? {
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.lease_name.clone()),
+                        ..Default::default()
+                    },
+                    spec: Some(self.spec(now, now, 0)),
+                };
+                self.api.create(&PostParams::default(), &lease).await
+            }
+            Some(mut lease) => {
+                let spec = lease.spec.clone().unwrap_or_default();
+                let held = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+                if !held && !lease_expired(&spec, now) {
+                    return Ok(false);
+                }
+                let transitions = spec.lease_transitions.unwrap_or(0);
+                lease.spec = Some(if held {
+                    let acquire_time = spec.acquire_time.map(|t| t.0).unwrap_or(now);
+                    self.spec(acquire_time, now, transitions)
+                } else {
+                    self.spec(now, now, transitions + 1)
+                });
+                // The replace carries the resourceVersion, so a replica racing us gets a conflict
+                self.api
+                    .replace(&self.lease_name, &PostParams::default(), &lease)
+                    .await
+            }
+        };
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Waits until this replica becomes the leader
+    pub async fn acquire(&self) {
+        info!(
+            "Waiting to acquire lease {} as {}",
+            self.lease_name, self.identity
+        );
+        loop {
+            match self.try_acquire().await {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) => error!("Failed to acquire lease {}: {:?}", self.lease_name, e),
+            }
+            tokio::time::sleep(self.renew_period).await;
+        }
+        self.leader.store(true, Ordering::SeqCst);
+        info!("Acquired lease {}, now leading", self.lease_name);
+    }
+
+    /// Keeps renewing the lease, returning once leadership is lost.
+    /// Gives up early if the lease cannot be renewed, before another replica could take it over.
+    pub async fn hold(&self) {
+        let renew_deadline = self.lease_duration * 2 / 3;
+        let mut last_renewed = Instant::now();
+        loop {
+            tokio::time::sleep(self.renew_period).await;
+            match self.try_acquire().await {
+                Ok(true) => last_renewed = Instant::now(),
+                Ok(false) => {
+                    warn!(
+                        "Lease {} was taken over by another replica",
+                        self.lease_name
+                    );
+                    break;
+                }
+                Err(e) => {
+                    error!("Failed to renew lease {}: {:?}", self.lease_name, e);
+                    if last_renewed.elapsed() >= renew_deadline {
+                        break;
+                    }
+                }
+            }
+        }
+        self.leader.store(false, Ordering::SeqCst);
+    }
+
+    /// Gives up the lease so a standby replica can take over without waiting for it to expire
+    pub async fn release(&self) {
+        self.leader.store(false, Ordering::SeqCst);
+        let lease = match self.api.get_opt(&self.lease_name).await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to release lease {}: {:?}", self.lease_name, e);
+                return;
+            }
+        };
+        let mut spec = lease.spec.clone().unwrap_or_default();
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return;
+        }
+        spec.holder_identity = None;
+        let lease = Lease {
+            spec: Some(spec),
+            ..lease
+        };
+        match self
+            .api
+            .replace(&self.lease_name, &PostParams::default(), &lease)
+            .await
+        {
+            Ok(_) => info!("Released lease {}", self.lease_name),
+            Err(e) => error!("Failed to release lease {}: {:?}", self.lease_name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_expired() {
+        let now = Utc::now();
+        let lease = |holder: Option<&str>, renewed_ago: i64| LeaseSpec {
+            holder_identity: holder.map(str::to_string),
+            lease_duration_seconds: Some(15),
+            renew_time: Some(MicroTime(now - chrono::Duration::seconds(renewed_ago))),
+            ..Default::default()
+        };
+
+        assert!(!lease_expired(&lease(Some("a"), 5), now));
+        assert!(lease_expired(&lease(Some("a"), 20), now));
+        // A released lease is free to take
+        assert!(lease_expired(&lease(None, 5), now));
+        assert!(lease_expired(&LeaseSpec::default(), now));
+    }
+}
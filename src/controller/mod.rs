@@ -1,3 +1,6 @@
 pub mod common;
 pub mod controller;
 pub mod crd_manager;
+pub mod leader;
+pub mod server;
+pub mod sharding;
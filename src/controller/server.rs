@@ -0,0 +1,36 @@
+use crate::shared::metrics;
+use axum::{Router, extract::State, http::StatusCode, routing::get};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+/// Serves the controller's health and readiness probes and its metrics.
+/// With leader election, only the leader reports ready.
+pub async fn run(
+    listener: tokio::net::TcpListener,
+    leader: Option<Arc<AtomicBool>>,
+) -> anyhow::Result<()> {
+    info!(
+        "Starting Controller probe server on {}",
+        listener.local_addr()?
+    );
+
+    let app = Router::new()
+        .route("/healthz", get(|| async { "OK" }))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics::handle_metrics))
+        .with_state(leader);
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn readyz(State(leader): State<Option<Arc<AtomicBool>>>) -> (StatusCode, &'static str) {
+    match leader {
+        Some(leader) if !leader.load(Ordering::SeqCst) => {
+            (StatusCode::SERVICE_UNAVAILABLE, "standby")
+        }
+        _ => (StatusCode::OK, "OK"),
+    }
+}
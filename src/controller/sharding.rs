@@ -0,0 +1,122 @@
+use crate::shared::settings::WorkerServiceSettings;
+use crate::shared::sharding::WorkerShards;
+use futures::StreamExt;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use kube::{
+    Api, Client,
+    runtime::{WatchStreamExt, reflector, watcher},
+};
+use std::collections::BTreeSet;
+use tracing::{error, info};
+
+/// Collects the base URLs of the ready endpoints in the slices
+pub fn ready_endpoints<'a>(
+    slices: impl IntoIterator<Item = &'a EndpointSlice>,
+    service: &WorkerServiceSettings,
+) -> BTreeSet<String> {
+    let scheme = service.scheme.as_deref().unwrap_or("http");
+    let mut endpoints = BTreeSet::new();
+    for slice in slices {
+        let port = service.port.map(i32::from).or_else(|| {
+            slice
+                .ports
+                .as_ref()
+                .and_then(|ports| ports.first())
+                .and_then(|p| p.port)
+        });
+        let Some(port) = port else {
+            continue;
+        };
+        for endpoint in &slice.endpoints {
+            // Endpoints without conditions are considered ready
+            let ready = endpoint
+                .conditions
+                .as_ref()
+                .and_then(|c| c.ready)
+                .unwrap_or(true);
+            if !ready {
+                continue;
+            }
+            for address in &endpoint.addresses {
+                let host = if address.contains(':') {
+                    format!("[{}]", address)
+                } else {
+                    address.clone()
+                };
+                endpoints.insert(format!("{}://{}:{}", scheme, host, port));
+            }
+        }
+    }
+    endpoints
+}
+
+/// Watches the worker Service's EndpointSlices and rebalances the ring when replicas come or go
+pub async fn watch_workers(
+    client: Client,
+    service: WorkerServiceSettings,
+    shards: std::sync::Arc<WorkerShards>,
+) {
+    let api: Api<EndpointSlice> = Api::namespaced(client, &service.namespace);
+    let config =
+        watcher::Config::default().labels(&format!("kubernetes.io/service-name={}", service.name));
+    let (reader, writer) = reflector::store();
+
+    info!(
+        "Discovering workers from service {}/{}",
+        service.namespace, service.name
+    );
+    reflector(writer, watcher(api, config))
+        .default_backoff()
+        .for_each(|event| {
+            match event {
+                // Until the first listing is done the store only holds some of the slices
+                Ok(watcher::Event::Init | watcher::Event::InitApply(_)) if !shards.is_synced() => {}
+                Ok(_) => {
+                    let slices = reader.state();
+                    let endpoints = ready_endpoints(slices.iter().map(|s| s.as_ref()), &service);
+                    let count = endpoints.len();
+                    if shards.update(endpoints) {
+                        info!("Rebalanced monitors across {} worker replicas", count);
+                    }
+                }
+                Err(e) => error!("Failed to watch worker endpoints: {:?}", e),
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ready_endpoints() {
+        let slice: EndpointSlice = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "worker-abc" },
+            "addressType": "IPv4",
+            "ports": [{ "port": 3000 }],
+            "endpoints": [
+                { "addresses": ["10.0.0.1"], "conditions": { "ready": true } },
+                { "addresses": ["10.0.0.2"], "conditions": { "ready": false } },
+                { "addresses": ["10.0.0.3"] }
+            ]
+        }))
+        .unwrap();
+        let service = WorkerServiceSettings {
+            namespace: "kastlewatch".to_string(),
+            name: "worker".to_string(),
+            port: None,
+            scheme: None,
+        };
+
+        assert_eq!(
+            ready_endpoints([&slice], &service),
+            endpoints(&["http://10.0.0.1:3000", "http://10.0.0.3:3000"])
+        );
+    }
+}
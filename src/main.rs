@@ -47,12 +47,29 @@ async fn main() -> anyhow::Result<()> {
                 error!("Failed to initialize TCPMonitor CRD: {:?}", e);
                 return Err(e);
             }
+            if let Err(e) = controller::crd_manager::init_crd(
+                client.clone(),
+                shared::resources::monitors::http_monitor::crd(),
+            )
+            .await
+            {
+                error!("Failed to initialize HTTPMonitor CRD: {:?}", e);
+                return Err(e);
+            }
             if let Err(e) = controller::crd_manager::init_crds::<
-                shared::resources::monitors::http_monitor::v1alpha1::HTTPMonitor,
+                shared::resources::monitors::dns_monitor::v1alpha1::DNSMonitor,
             >(client.clone())
             .await
             {
-                error!("Failed to initialize HTTPMonitor CRD: {:?}", e);
+                error!("Failed to initialize DNSMonitor CRD: {:?}", e);
+                return Err(e);
+            }
+            if let Err(e) = controller::crd_manager::init_crds::<
+                shared::resources::monitors::tls_certificate_monitor::v1alpha1::TLSCertificateMonitor,
+            >(client.clone())
+            .await
+            {
+                error!("Failed to initialize TLSCertificateMonitor CRD: {:?}", e);
                 return Err(e);
             }
             if let Err(e) = controller::crd_manager::init_crds::<
@@ -63,6 +80,22 @@ async fn main() -> anyhow::Result<()> {
                 error!("Failed to initialize DiscordNotifier CRD: {:?}", e);
                 return Err(e);
             }
+            if let Err(e) = controller::crd_manager::init_crds::<
+                shared::resources::notifiers::slack_notifier::v1alpha1::SlackNotifier,
+            >(client.clone())
+            .await
+            {
+                error!("Failed to initialize SlackNotifier CRD: {:?}", e);
+                return Err(e);
+            }
+            if let Err(e) = controller::crd_manager::init_crds::<
+                shared::resources::notifiers::webhook_notifier::v1alpha1::WebhookNotifier,
+            >(client.clone())
+            .await
+            {
+                error!("Failed to initialize WebhookNotifier CRD: {:?}", e);
+                return Err(e);
+            }
 
             // Run Controller
             if let Err(e) = controller::controller::run(client, settings).await {
@@ -72,10 +105,15 @@ async fn main() -> anyhow::Result<()> {
         }
         Commands::Worker => {
             info!("Starting KastleWatch Worker");
-            let client = Client::try_default().await?;
+            let client = if settings.worker.probe_only.unwrap_or(false) {
+                info!("Running in probe-only mode without Kubernetes credentials");
+                None
+            } else {
+                Some(Client::try_default().await?)
+            };
             let addr = format!("{}:{}", settings.worker.host, settings.worker.port);
             let listener = tokio::net::TcpListener::bind(&addr).await?;
-            if let Err(e) = kastlewatch::worker::server::run(client, listener).await {
+            if let Err(e) = kastlewatch::worker::server::run(client, listener, settings).await {
                 error!("Worker failed: {:?}", e);
                 return Err(e);
             }
@@ -88,10 +126,20 @@ async fn main() -> anyhow::Result<()> {
                     &shared::resources::monitors::tcp_monitor::v1alpha1::TCPMonitor::crd()
                 )?
             );
+            println!(
+                "---\n{}",
+                serde_yaml::to_string(&shared::resources::monitors::http_monitor::crd())?
+            );
+            println!(
+                "---\n{}",
+                serde_yaml::to_string(
+                    &shared::resources::monitors::dns_monitor::v1alpha1::DNSMonitor::crd()
+                )?
+            );
             println!(
                 "---\n{}",
                 serde_yaml::to_string(
-                    &shared::resources::monitors::http_monitor::v1alpha1::HTTPMonitor::crd()
+                    &shared::resources::monitors::tls_certificate_monitor::v1alpha1::TLSCertificateMonitor::crd()
                 )?
             );
             println!(
@@ -101,6 +149,19 @@ async fn main() -> anyhow::Result<()> {
                     )
                 )?
             );
+            println!(
+                "---\n{}",
+                serde_yaml::to_string(
+                    &shared::resources::notifiers::slack_notifier::v1alpha1::SlackNotifier::crd()
+                )?
+            );
+            println!(
+                "---\n{}",
+                serde_yaml::to_string(
+                    &shared::resources::notifiers::webhook_notifier::v1alpha1::WebhookNotifier::crd(
+                    )
+                )?
+            );
         }
     }
 
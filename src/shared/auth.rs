@@ -0,0 +1,511 @@
+use crate::shared::context::AppState;
+use crate::shared::resources::common::SecretKeySelector;
+use crate::shared::resources::notifiers;
+use crate::shared::settings::{
+    AuthSettings, ClientTlsSettings, ProbeAccessSettings, ServerTlsSettings, Settings,
+};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use kube::Client;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Header carrying the signature of a dispatched request
+pub const SIGNATURE_HEADER: &str = "X-KastleWatch-Signature";
+
+const DEFAULT_MAX_SKEW: u64 = 60;
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+/// Same limit as reqwest's default redirect policy
+const MAX_REDIRECTS: usize = 10;
+
+/// The shared key used to sign and verify controller-to-worker requests
+pub struct DispatchKey {
+    key: Vec<u8>,
+    max_skew: u64,
+    /// Signatures already accepted, with their timestamp, so a captured request cannot be replayed.
+    /// Each worker replica remembers its own, so within max_skew a request can still be replayed
+    /// once against every other replica sharing the key.
+    seen: Mutex<HashMap<String, i64>>,
+}
+
+impl DispatchKey {
+    pub fn new(key: impl Into<Vec<u8>>, max_skew: Option<u64>) -> Self {
+        DispatchKey {
+            key: key.into(),
+            max_skew: max_skew.unwrap_or(DEFAULT_MAX_SKEW),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads the key from the file or Kubernetes Secret configured in the auth settings
+    pub async fn load(client: Option<Client>, auth: &AuthSettings) -> anyhow::Result<Self> {
+        let key = match (&auth.key_file, client) {
+            (Some(key_file), _) => std::fs::read_to_string(key_file)?,
+            (None, Some(client)) => {
+                let (Some(namespace), Some(name), Some(key)) =
+                    (&auth.secret_namespace, &auth.secret_name, &auth.secret_key)
+                else {
+                    return Err(anyhow::anyhow!(
+                        "auth requires either key_file or secret_namespace, secret_name and secret_key"
+                    ));
+                };
+                let secret_ref = SecretKeySelector {
+                    name: name.clone(),
+                    key: key.clone(),
+                };
+                notifiers::get_secret_value(client, namespace, &secret_ref).await?
+            }
+            (None, None) => {
+                return Err(anyhow::anyhow!(
+                    "auth.key_file is required when running without Kubernetes credentials"
+                ));
+            }
+        };
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("Dispatch key is empty"));
+        }
+        Ok(DispatchKey::new(key.into_bytes(), auth.max_skew))
+    }
+
+    /// HMAC-SHA256 over "<timestamp>.<METHOD>.<path and query>.<body>"
+    fn mac(
+        &self,
+        timestamp: i64,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let pkey = PKey::hmac(&self.key)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey)?;
+        signer.update(format!("{}.{}.{}.", timestamp, method, path).as_bytes())?;
+        signer.update(body)?;
+        Ok(signer.sign_to_vec()?)
+    }
+
+    /// Signs a request, returning the value for the signature header: "t=<unix time>,v1=<hex HMAC-SHA256>"
+    pub fn sign(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        timestamp: i64,
+    ) -> anyhow::Result<String> {
+        Ok(format!(
+            "t={},v1={}",
+            timestamp,
+            to_hex(&self.mac(timestamp, method, path, body)?)
+        ))
+    }
+
+    /// Verifies a signature header against the request, rejecting signatures outside the allowed clock skew
+    /// and signatures this replica already accepted once
+    pub fn verify(
+        &self,
+        header: &str,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        now: i64,
+    ) -> anyhow::Result<()> {
+        let mut timestamp = None;
+        let mut signature = None;
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signature = Some(value),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or_else(|| anyhow::anyhow!("Missing signature timestamp"))?;
+        let signature = signature.ok_or_else(|| anyhow::anyhow!("Missing signature"))?;
+
+        if now.abs_diff(timestamp) > self.max_skew {
+            return Err(anyhow::anyhow!("Signature timestamp outside allowed skew"));
+        }
+
+        let expected = to_hex(&self.mac(timestamp, method, path, body)?);
+        if expected.len() != signature.len()
+            || !openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+        {
+            return Err(anyhow::anyhow!("Signature mismatch"));
+        }
+
+        // Signatures older than the skew are rejected above, so only those need remembering
+        let mut seen = self.seen.lock().unwrap();
+        let max_skew = self.max_skew as i64;
+        seen.retain(|_, t| now - *t <= max_skew);
+        if seen.insert(expected, timestamp).is_some() {
+            return Err(anyhow::anyhow!("Signature already used"));
+        }
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A target the /probe endpoint may reach
+#[derive(Debug, Clone, PartialEq)]
+enum TargetPattern {
+    /// An address or CIDR range, matching IP address targets
+    Network(IpAddr, u32),
+    /// A host name, or "*.<domain>" matching its subdomains
+    Host(String),
+}
+
+impl TargetPattern {
+    fn parse(pattern: &str) -> anyhow::Result<Self> {
+        let (address, prefix) = match pattern.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (pattern, None),
+        };
+        let Ok(address) = address.parse::<IpAddr>() else {
+            if prefix.is_some() {
+                return Err(anyhow::anyhow!("invalid CIDR range {}", pattern));
+            }
+            return Ok(TargetPattern::Host(pattern.to_ascii_lowercase()));
+        };
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u32>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| anyhow::anyhow!("invalid CIDR range {}", pattern))?,
+            None => bits,
+        };
+        Ok(TargetPattern::Network(address, prefix))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            TargetPattern::Network(network, prefix) => match (network, host.parse::<IpAddr>()) {
+                (IpAddr::V4(network), Ok(IpAddr::V4(address))) => {
+                    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                    u32::from(*network) & mask == u32::from(address) & mask
+                }
+                (IpAddr::V6(network), Ok(IpAddr::V6(address))) => {
+                    let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                    u128::from(*network) & mask == u128::from(address) & mask
+                }
+                _ => false,
+            },
+            TargetPattern::Host(pattern) => {
+                let host = host.to_ascii_lowercase();
+                match pattern.strip_prefix("*.") {
+                    Some(domain) => host
+                        .strip_suffix(domain)
+                        .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+                    None => host == *pattern,
+                }
+            }
+        }
+    }
+}
+
+/// Access control of the /probe endpoint, which connects to whatever target the caller names
+#[derive(Debug, Default)]
+pub struct ProbeAccess {
+    bearer_token: Option<String>,
+    /// Not set when any target may be probed
+    allowed_targets: Option<Vec<TargetPattern>>,
+}
+
+impl ProbeAccess {
+    /// Reads the token file and parses the target patterns of the probe access settings
+    pub fn load(settings: Option<&ProbeAccessSettings>) -> anyhow::Result<Self> {
+        let Some(settings) = settings else {
+            return Ok(ProbeAccess::default());
+        };
+        let bearer_token = match &settings.bearer_token_file {
+            Some(file) => {
+                let token = std::fs::read_to_string(file)?.trim().to_string();
+                if token.is_empty() {
+                    return Err(anyhow::anyhow!("Probe bearer token is empty"));
+                }
+                Some(token)
+            }
+            None => None,
+        };
+        let allowed_targets = settings
+            .allowed_targets
+            .as_ref()
+            .map(|patterns| {
+                patterns
+                    .iter()
+                    .map(|pattern| TargetPattern::parse(pattern))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?;
+        Ok(ProbeAccess {
+            bearer_token,
+            allowed_targets,
+        })
+    }
+
+    /// Whether the Authorization header carries the configured token, if any
+    pub fn authorizes(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = &self.bearer_token else {
+            return true;
+        };
+        let presented = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        presented.len() == token.len()
+            && openssl::memcmp::eq(presented.as_bytes(), token.as_bytes())
+    }
+
+    /// Whether a target host, optionally with a port, may be probed
+    pub fn allows(&self, host: &str) -> bool {
+        let Some(patterns) = &self.allowed_targets else {
+            return true;
+        };
+        let host = match host.parse::<SocketAddr>() {
+            Ok(address) => address.ip().to_string(),
+            Err(_) => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+        };
+        patterns.iter().any(|pattern| pattern.matches(&host))
+    }
+
+    /// Follows redirects like reqwest's default policy, as long as every hop is an allowed target
+    pub fn redirect_policy(access: Arc<ProbeAccess>) -> reqwest::redirect::Policy {
+        reqwest::redirect::Policy::custom(move |attempt| {
+            let host = attempt.url().host_str().unwrap_or_default().to_string();
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if access.allows(&host) {
+                attempt.follow()
+            } else {
+                attempt.error(format!("redirect to {} is not an allowed target", host))
+            }
+        })
+    }
+}
+
+/// Middleware rejecting /probe requests without the configured bearer token
+pub async fn require_probe_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    if !state.probe_access.authorizes(authorization) {
+        warn!("Rejected unauthenticated probe request");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Middleware rejecting worker API requests without a valid signature when a dispatch key is configured
+pub async fn require_signature(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = state.dispatch_key.clone() else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+
+    let header = parts
+        .headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    if let Err(e) = key.verify(
+        header,
+        parts.method.as_str(),
+        path,
+        &bytes,
+        chrono::Utc::now().timestamp(),
+    ) {
+        warn!("Rejected unauthenticated request to {}: {}", parts.uri, e);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Builds the HTTP client the controller uses to reach the worker, with the client certificate for mTLS if configured
+pub fn build_worker_client(tls: Option<&ClientTlsSettings>) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(tls) = tls {
+        if let Some(ca_file) = &tls.ca_file {
+            let ca = std::fs::read(ca_file)?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&ca)?);
+        }
+        if let (Some(cert_file), Some(key_file)) = (&tls.cert_file, &tls.key_file) {
+            let cert = std::fs::read_to_string(cert_file)?;
+            let key = std::fs::read_to_string(key_file)?;
+            builder = builder.identity(client_identity(&cert, &key)?);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Builds the mTLS identity from PEM, as reqwest only reads PKCS#8 keys while cert-manager and
+/// `openssl genrsa` write PKCS#1 by default
+pub fn client_identity(cert: &str, key: &str) -> anyhow::Result<reqwest::Identity> {
+    let key = PKey::private_key_from_pem(key.as_bytes())
+        .map_err(|e| anyhow::anyhow!("invalid client key: {}", e))?
+        .private_key_to_pem_pkcs8()?;
+    Ok(reqwest::Identity::from_pkcs8_pem(cert.as_bytes(), &key)?)
+}
+
+/// Builds the worker's TLS acceptor, requiring client certificates signed by the client CA if configured
+pub fn build_server_tls(tls: &ServerTlsSettings) -> anyhow::Result<Arc<SslAcceptor>> {
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    acceptor.set_certificate_chain_file(&tls.cert_file)?;
+    acceptor.set_private_key_file(&tls.key_file, SslFiletype::PEM)?;
+    acceptor.check_private_key()?;
+    if let Some(client_ca_file) = &tls.client_ca_file {
+        acceptor.set_ca_file(client_ca_file)?;
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(Arc::new(acceptor.build()))
+}
+
+/// Loads the dispatch key if authentication is configured
+pub async fn load_dispatch_key(
+    client: Option<Client>,
+    settings: &Settings,
+) -> anyhow::Result<Option<Arc<DispatchKey>>> {
+    match &settings.auth {
+        Some(auth) => Ok(Some(Arc::new(DispatchKey::load(client, auth).await?))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = DispatchKey::new("secret", None);
+        let body = br#"{"kind":"TCPMonitor"}"#;
+        let path = "/v1alpha1/tcpmonitor";
+        let header = key.sign("POST", path, body, 1_700_000_000).unwrap();
+
+        // Tampered body
+        assert!(
+            key.verify(&header, "POST", path, b"{}", 1_700_000_010)
+                .is_err()
+        );
+        // Sent to another endpoint
+        assert!(
+            key.verify(
+                &header,
+                "POST",
+                "/v1alpha1/tcpmonitor/probe",
+                body,
+                1_700_000_010
+            )
+            .is_err()
+        );
+        // Replayed long after signing
+        assert!(
+            key.verify(&header, "POST", path, body, 1_700_001_000)
+                .is_err()
+        );
+        // Signed with another key
+        let other = DispatchKey::new("other", None);
+        assert!(
+            other
+                .verify(&header, "POST", path, body, 1_700_000_010)
+                .is_err()
+        );
+        // Garbage header
+        assert!(
+            key.verify("v1=abc", "POST", path, body, 1_700_000_010)
+                .is_err()
+        );
+
+        assert!(
+            key.verify(&header, "POST", path, body, 1_700_000_010)
+                .is_ok()
+        );
+        // Replayed within the skew
+        assert!(
+            key.verify(&header, "POST", path, body, 1_700_000_011)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_probe_access() {
+        let settings = ProbeAccessSettings {
+            bearer_token_file: None,
+            allowed_targets: Some(vec![
+                "10.0.0.0/8".to_string(),
+                "fd00::/8".to_string(),
+                "192.168.1.10".to_string(),
+                "*.example.com".to_string(),
+                "status.example.org".to_string(),
+            ]),
+        };
+        let access = ProbeAccess::load(Some(&settings)).unwrap();
+        assert!(access.allows("10.1.2.3"));
+        assert!(access.allows("10.1.2.3:5432"));
+        assert!(access.allows("[fd00::1]"));
+        assert!(access.allows("192.168.1.10"));
+        assert!(access.allows("api.Example.com"));
+        assert!(access.allows("status.example.org"));
+        assert!(!access.allows("11.0.0.1"));
+        assert!(!access.allows("192.168.1.11"));
+        assert!(!access.allows("169.254.169.254"));
+        assert!(!access.allows("example.com"));
+        assert!(!access.allows("evil-example.com"));
+        assert!(!access.allows("www.status.example.org"));
+
+        // Without settings, anything goes
+        let access = ProbeAccess::load(None).unwrap();
+        assert!(access.allows("169.254.169.254"));
+        assert!(access.authorizes(None));
+
+        let access = ProbeAccess {
+            bearer_token: Some("token".to_string()),
+            allowed_targets: None,
+        };
+        assert!(access.authorizes(Some("Bearer token")));
+        assert!(!access.authorizes(Some("Bearer other")));
+        assert!(!access.authorizes(None));
+
+        for pattern in ["10.0.0.0/33", "10.0.0.0/x", "example.com/8"] {
+            let settings = ProbeAccessSettings {
+                bearer_token_file: None,
+                allowed_targets: Some(vec![pattern.to_string()]),
+            };
+            assert!(ProbeAccess::load(Some(&settings)).is_err());
+        }
+    }
+}
@@ -1,12 +1,64 @@
-use crate::shared::settings::Settings;
+use crate::shared::auth::{self, DispatchKey, ProbeAccess};
+use crate::shared::queue::WorkQueue;
+use crate::shared::settings::{ProbeModuleSettings, Settings};
+use crate::shared::sharding::WorkerShards;
 use kube::Client;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct Context {
     pub client: Client,
     pub settings: Settings,
+    pub dispatch: WorkerDispatch,
 }
+
+/// How the controller reaches the worker
+#[derive(Clone, Default)]
+pub struct WorkerDispatch {
+    pub http_client: reqwest::Client,
+    /// Key the request body is signed with, if authentication is configured
+    pub signing_key: Option<Arc<DispatchKey>>,
+    /// Consecutive dispatch failures per monitor, used to back off retries
+    pub failures: Arc<Mutex<HashMap<String, DispatchFailures>>>,
+    /// Worker replicas discovered from the worker Service, if sharding is configured
+    pub shards: Option<Arc<WorkerShards>>,
+}
+
+/// The dispatch failures of a monitor since its last successful dispatch
+#[derive(Clone, Copy, Debug)]
+pub struct DispatchFailures {
+    pub count: u32,
+    /// Reconciles before this time, such as those triggered by the status patch, do not dispatch
+    pub retry_at: Instant,
+}
+
+impl WorkerDispatch {
+    pub async fn new(client: Client, settings: &Settings) -> anyhow::Result<Self> {
+        Ok(WorkerDispatch {
+            http_client: auth::build_worker_client(settings.controller.tls.as_ref())?,
+            signing_key: auth::load_dispatch_key(Some(client), settings).await?,
+            failures: Default::default(),
+            shards: settings
+                .controller
+                .worker_service
+                .as_ref()
+                .map(|_| Arc::new(WorkerShards::default())),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
-    pub client: Client,
+    /// Not set when the worker runs in probe-only mode without Kubernetes credentials
+    pub client: Option<Client>,
+    /// Key requests are verified against, if authentication is configured
+    pub dispatch_key: Option<Arc<DispatchKey>>,
+    /// Checks accepted by the worker
+    pub queue: Arc<WorkQueue>,
+    /// Modules of the blackbox_exporter compatible /probe endpoint
+    pub modules: Arc<BTreeMap<String, ProbeModuleSettings>>,
+    /// Token and target allowlist of the /probe endpoint
+    pub probe_access: Arc<ProbeAccess>,
 }
@@ -0,0 +1,380 @@
+use axum::http::header;
+use axum::response::IntoResponse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Label names and values of a single series
+pub type Labels = Vec<(&'static str, String)>;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Buckets in seconds for probe and dispatch durations
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+fn write_labels(out: &mut String, labels: &Labels, extra: Option<(&str, &str)>) {
+    let mut pairs: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    pairs.extend(extra);
+    if pairs.is_empty() {
+        return;
+    }
+    out.push('{');
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = write!(out, "{}=\"{}\"", name, escaped);
+    }
+    out.push('}');
+}
+
+/// Whether a series belongs to the given labels, e.g. a transition series to its monitor
+fn belongs_to(series: &Labels, labels: &Labels) -> bool {
+    series.starts_with(labels)
+}
+
+fn write_header(out: &mut String, name: &str, type_: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, type_);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    values: Mutex<BTreeMap<Labels, u64>>,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Counter {
+            name,
+            help,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, labels: Labels) {
+        *self.values.lock().unwrap().entry(labels).or_default() += 1;
+    }
+
+    /// Removes the series starting with the given labels
+    pub fn remove(&self, labels: &Labels) {
+        self.values
+            .lock()
+            .unwrap()
+            .retain(|series, _| !belongs_to(series, labels));
+    }
+
+    fn encode(&self, out: &mut String) {
+        write_header(out, self.name, "counter", self.help);
+        for (labels, value) in self.values.lock().unwrap().iter() {
+            out.push_str(self.name);
+            out.push_str("_total");
+            write_labels(out, labels, None);
+            let _ = writeln!(out, " {}", value);
+        }
+    }
+}
+
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    values: Mutex<BTreeMap<Labels, f64>>,
+}
+
+impl Gauge {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set(&self, labels: Labels, value: f64) {
+        self.values.lock().unwrap().insert(labels, value);
+    }
+
+    /// Removes the series starting with the given labels
+    pub fn remove(&self, labels: &Labels) {
+        self.values
+            .lock()
+            .unwrap()
+            .retain(|series, _| !belongs_to(series, labels));
+    }
+
+    fn encode(&self, out: &mut String) {
+        write_header(out, self.name, "gauge", self.help);
+        for (labels, value) in self.values.lock().unwrap().iter() {
+            out.push_str(self.name);
+            write_labels(out, labels, None);
+            let _ = writeln!(out, " {}", value);
+        }
+    }
+}
+
+#[derive(Default)]
+struct HistogramValue {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: &'static [f64],
+    values: Mutex<BTreeMap<Labels, HistogramValue>>,
+}
+
+impl Histogram {
+    const fn new(name: &'static str, help: &'static str, bounds: &'static [f64]) -> Self {
+        Histogram {
+            name,
+            help,
+            bounds,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, labels: Labels, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut values = self.values.lock().unwrap();
+        let value = values.entry(labels).or_insert_with(|| HistogramValue {
+            buckets: vec![0; self.bounds.len()],
+            ..Default::default()
+        });
+        for (bucket, bound) in value.buckets.iter_mut().zip(self.bounds) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        value.sum += seconds;
+        value.count += 1;
+    }
+
+    /// Removes the series starting with the given labels
+    pub fn remove(&self, labels: &Labels) {
+        self.values
+            .lock()
+            .unwrap()
+            .retain(|series, _| !belongs_to(series, labels));
+    }
+
+    fn encode(&self, out: &mut String) {
+        write_header(out, self.name, "histogram", self.help);
+        for (labels, value) in self.values.lock().unwrap().iter() {
+            for (bucket, bound) in value.buckets.iter().zip(self.bounds) {
+                out.push_str(self.name);
+                out.push_str("_bucket");
+                write_labels(out, labels, Some(("le", &bound.to_string())));
+                let _ = writeln!(out, " {}", bucket);
+            }
+            out.push_str(self.name);
+            out.push_str("_bucket");
+            write_labels(out, labels, Some(("le", "+Inf")));
+            let _ = writeln!(out, " {}", value.count);
+            out.push_str(self.name);
+            out.push_str("_sum");
+            write_labels(out, labels, None);
+            let _ = writeln!(out, " {}", value.sum);
+            out.push_str(self.name);
+            out.push_str("_count");
+            write_labels(out, labels, None);
+            let _ = writeln!(out, " {}", value.count);
+        }
+    }
+}
+
+/// The metrics exported by the worker and the controller.
+/// Each process only reports the series it records.
+pub struct Metrics {
+    /// How long probes took, retries included
+    pub probe_duration: Histogram,
+    /// 1 while a monitor is Healthy or Warning, 0 otherwise
+    pub monitor_up: Gauge,
+    /// Monitor state changes
+    pub state_transitions: Counter,
+    /// Notifications sent, by notifier kind and result
+    pub notifications: Counter,
+    /// How long dispatching a check to a worker took
+    pub dispatch_duration: Histogram,
+    /// Failed reconciliations, by kind and reason
+    pub reconcile_errors: Counter,
+    /// When the series of each monitor go stale, as a worker never learns that a monitor was deleted
+    expiries: Mutex<BTreeMap<Labels, Instant>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            probe_duration: Histogram::new(
+                "kastlewatch_probe_duration_seconds",
+                "Duration of monitor probes, retries included.",
+                DURATION_BUCKETS,
+            ),
+            monitor_up: Gauge::new(
+                "kastlewatch_monitor_up",
+                "Whether the monitor target is up (Healthy or Warning).",
+            ),
+            state_transitions: Counter::new(
+                "kastlewatch_state_transitions",
+                "Monitor state transitions.",
+            ),
+            notifications: Counter::new(
+                "kastlewatch_notifications",
+                "Notifications sent, by notifier kind and result.",
+            ),
+            dispatch_duration: Histogram::new(
+                "kastlewatch_dispatch_duration_seconds",
+                "Duration of dispatching checks from the controller to workers.",
+                DURATION_BUCKETS,
+            ),
+            reconcile_errors: Counter::new(
+                "kastlewatch_reconcile_errors",
+                "Failed reconciliations, by kind and reason.",
+            ),
+            expiries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Keeps the series of a monitor for another ttl
+    pub fn touch_monitor(&self, labels: &Labels, ttl: Duration) {
+        self.expiries
+            .lock()
+            .unwrap()
+            .insert(labels.clone(), Instant::now() + ttl);
+    }
+
+    /// Removes the series of a monitor that was deleted
+    pub fn forget_monitor(&self, labels: &Labels) {
+        self.expiries.lock().unwrap().remove(labels);
+        self.probe_duration.remove(labels);
+        self.monitor_up.remove(labels);
+        self.state_transitions.remove(labels);
+    }
+
+    /// Removes the series of monitors that were not touched within their ttl
+    fn forget_stale_monitors(&self) {
+        let now = Instant::now();
+        let stale: Vec<Labels> = self
+            .expiries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, expiry)| **expiry <= now)
+            .map(|(labels, _)| labels.clone())
+            .collect();
+        for labels in stale {
+            self.forget_monitor(&labels);
+        }
+    }
+
+    /// Encodes all metrics in the OpenMetrics text format
+    pub fn encode(&self) -> String {
+        self.forget_stale_monitors();
+        let mut out = String::new();
+        self.probe_duration.encode(&mut out);
+        self.monitor_up.encode(&mut out);
+        self.state_transitions.encode(&mut out);
+        self.notifications.encode(&mut out);
+        self.dispatch_duration.encode(&mut out);
+        self.reconcile_errors.encode(&mut out);
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Labels identifying a monitor
+pub fn monitor_labels(kind: &str, namespace: &str, name: &str) -> Labels {
+    vec![
+        ("kind", kind.to_string()),
+        ("namespace", namespace.to_string()),
+        ("name", name.to_string()),
+    ]
+}
+
+/// Handler serving /metrics
+pub async fn handle_metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], METRICS.encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::new();
+        let labels = monitor_labels("TCPMonitor", "default", "db");
+        metrics
+            .probe_duration
+            .observe(labels.clone(), Duration::from_millis(20));
+        metrics.monitor_up.set(labels.clone(), 1.0);
+        metrics.state_transitions.inc(vec![
+            ("kind", "TCPMonitor".to_string()),
+            ("from", "NoData".to_string()),
+            ("to", "Healthy".to_string()),
+        ]);
+        metrics
+            .notifications
+            .inc(vec![("notifier_kind", "Say \"hi\"".to_string())]);
+
+        let text = metrics.encode();
+        assert!(text.contains("# TYPE kastlewatch_probe_duration_seconds histogram\n"));
+        assert!(text.contains(
+            "kastlewatch_probe_duration_seconds_bucket{kind=\"TCPMonitor\",namespace=\"default\",name=\"db\",le=\"0.01\"} 0\n"
+        ));
+        assert!(text.contains(
+            "kastlewatch_probe_duration_seconds_bucket{kind=\"TCPMonitor\",namespace=\"default\",name=\"db\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "kastlewatch_probe_duration_seconds_count{kind=\"TCPMonitor\",namespace=\"default\",name=\"db\"} 1\n"
+        ));
+        assert!(text.contains(
+            "kastlewatch_monitor_up{kind=\"TCPMonitor\",namespace=\"default\",name=\"db\"} 1\n"
+        ));
+        assert!(text.contains(
+            "kastlewatch_state_transitions_total{kind=\"TCPMonitor\",from=\"NoData\",to=\"Healthy\"} 1\n"
+        ));
+        assert!(
+            text.contains("kastlewatch_notifications_total{notifier_kind=\"Say \\\"hi\\\"\"} 1\n")
+        );
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_forget_monitor() {
+        let metrics = Metrics::new();
+        let db = monitor_labels("TCPMonitor", "default", "db");
+        let web = monitor_labels("TCPMonitor", "default", "web");
+        for labels in [&db, &web] {
+            metrics.monitor_up.set(labels.clone(), 1.0);
+            let mut transition_labels = labels.clone();
+            transition_labels.push(("from", "NoData".to_string()));
+            transition_labels.push(("to", "Healthy".to_string()));
+            metrics.state_transitions.inc(transition_labels);
+        }
+
+        metrics.forget_monitor(&db);
+        let text = metrics.encode();
+        assert!(!text.contains("name=\"db\""));
+        assert!(text.contains(
+            "kastlewatch_monitor_up{kind=\"TCPMonitor\",namespace=\"default\",name=\"web\"} 1\n"
+        ));
+        assert!(text.contains("kastlewatch_state_transitions_total{kind=\"TCPMonitor\",namespace=\"default\",name=\"web\","));
+
+        // A monitor no longer probed here goes away once its ttl is over
+        metrics.touch_monitor(&web, Duration::ZERO);
+        assert!(!metrics.encode().contains("name=\"web\""));
+    }
+}
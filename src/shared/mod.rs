@@ -1,3 +1,7 @@
+pub mod auth;
 pub mod context;
+pub mod metrics;
+pub mod queue;
 pub mod resources;
 pub mod settings;
+pub mod sharding;
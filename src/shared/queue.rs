@@ -0,0 +1,138 @@
+use crate::shared::settings::WorkerSettings;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+const DEFAULT_MAX_PER_HOST: usize = 4;
+const DEFAULT_QUEUE_SIZE: usize = 256;
+
+/// Why a check was not accepted by the worker
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// The worker has no room left in its queue
+    QueueFull,
+    /// The target host already has as many checks in flight as allowed
+    HostBusy,
+}
+
+#[derive(Default)]
+struct Pending {
+    total: usize,
+    per_host: HashMap<String, usize>,
+}
+
+/// Bounded queue of checks accepted by the worker.
+/// At most max_concurrency checks run at once, the rest wait in the queue.
+pub struct WorkQueue {
+    running: Semaphore,
+    capacity: usize,
+    max_per_host: usize,
+    pending: Mutex<Pending>,
+}
+
+impl WorkQueue {
+    pub fn new(max_concurrency: usize, queue_size: usize, max_per_host: usize) -> Self {
+        WorkQueue {
+            running: Semaphore::new(max_concurrency),
+            capacity: max_concurrency + queue_size,
+            max_per_host,
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    pub fn from_settings(settings: &WorkerSettings) -> Self {
+        WorkQueue::new(
+            settings.max_concurrency.unwrap_or(DEFAULT_MAX_CONCURRENCY),
+            settings.queue_size.unwrap_or(DEFAULT_QUEUE_SIZE),
+            settings.max_per_host.unwrap_or(DEFAULT_MAX_PER_HOST),
+        )
+    }
+
+    /// Reserves a slot for a check against the given host
+    pub fn try_admit(self: &Arc<Self>, host: &str) -> Result<Ticket, Rejection> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.total >= self.capacity {
+            return Err(Rejection::QueueFull);
+        }
+        let for_host = pending.per_host.entry(host.to_string()).or_default();
+        if *for_host >= self.max_per_host {
+            return Err(Rejection::HostBusy);
+        }
+        *for_host += 1;
+        pending.total += 1;
+
+        Ok(Ticket {
+            queue: self.clone(),
+            host: host.to_string(),
+        })
+    }
+
+    /// Whether the queue is full and new checks would be rejected
+    pub fn is_full(&self) -> bool {
+        self.pending.lock().unwrap().total >= self.capacity
+    }
+
+    /// The number of accepted checks that have not finished yet
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().total
+    }
+}
+
+/// A reserved slot in the queue, released when dropped
+pub struct Ticket {
+    queue: Arc<WorkQueue>,
+    host: String,
+}
+
+impl Ticket {
+    /// Waits for a free worker slot, then runs the check
+    pub async fn run<F: Future>(self, check: F) -> F::Output {
+        let _permit = self.queue.running.acquire().await;
+        check.await
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut pending = self.queue.pending.lock().unwrap();
+        pending.total -= 1;
+        if let Some(for_host) = pending.per_host.get_mut(&self.host) {
+            *for_host -= 1;
+            if *for_host == 0 {
+                pending.per_host.remove(&self.host);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_limits() {
+        let queue = Arc::new(WorkQueue::new(1, 2, 2));
+
+        let a1 = queue.try_admit("a").unwrap();
+        let _a2 = queue.try_admit("a").unwrap();
+        assert_eq!(queue.try_admit("a").err(), Some(Rejection::HostBusy));
+
+        let _b1 = queue.try_admit("b").unwrap();
+        assert!(queue.is_full());
+        assert_eq!(queue.try_admit("c").err(), Some(Rejection::QueueFull));
+
+        drop(a1);
+        assert!(!queue.is_full());
+        assert_eq!(queue.pending(), 2);
+        assert!(queue.try_admit("a").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_ticket_released_after_run() {
+        let queue = Arc::new(WorkQueue::new(1, 0, 1));
+        let ticket = queue.try_admit("a").unwrap();
+        assert_eq!(ticket.run(async { 42 }).await, 42);
+        assert_eq!(queue.pending(), 0);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::shared::context::{AppState, Context};
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    response::Response,
 };
 use kube::Resource;
 use kube::runtime::controller::Action;
@@ -10,16 +10,79 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 /// Configuration for the monitoring behavior
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 pub struct MonitorConfigSpec {
     /// Timeout in seconds for the connection attempt
     pub timeout: u32,
-    /// Number of retries before considering the check failed
+    /// Number of retries before considering the check failed. Retries that would not finish within polling_frequency are skipped.
     pub retries: u32,
+    /// Delay in seconds before the first retry. Optional. Defaults to 1.
+    pub retry_delay: Option<u32>,
+    /// Factor the retry delay is multiplied by after each retry. Optional. Defaults to 2. Use 1 for a fixed delay.
+    pub retry_backoff: Option<u32>,
     /// Frequency in seconds to poll the target
     pub polling_frequency: u32,
+    /// Number of consecutive failed checks before the monitor goes Critical. Optional. Defaults to 1.
+    pub failure_threshold: Option<u32>,
+    /// Number of consecutive successful checks before a failing monitor recovers. Optional. Defaults to 1.
+    pub success_threshold: Option<u32>,
+    /// Flap detection settings. Optional. If not defined, flapping is not detected.
+    pub flap_detection: Option<FlapDetectionSpec>,
     /// Labels to match notifiers
     pub notifiers_match_labels: Option<std::collections::BTreeMap<String, String>>,
+    /// Labels to match the notifiers told about check errors (a broken monitor rather than a broken target).
+    /// Optional. If not defined, check errors go to the notifiers matched by notifiers_match_labels.
+    pub error_notifiers_match_labels: Option<std::collections::BTreeMap<String, String>>,
+    /// Names of the worker locations to probe from. Optional. Defaults to all locations configured in the controller.
+    pub locations: Option<Vec<String>>,
+    /// Number of locations that must see a failure before the monitor goes Critical or Warning.
+    /// Optional. Defaults to a majority of the locations that returned a result. Must be at most the
+    /// number of selected locations. If fewer locations return a result, the monitor goes to Error.
+    pub location_quorum: Option<u32>,
+}
+
+impl MonitorConfigSpec {
+    /// Checks the selected locations against the configured ones, and that enough are selected to reach the quorum
+    pub fn validate_locations(&self, configured: &[&str]) -> anyhow::Result<()> {
+        for name in self.locations.iter().flatten() {
+            if !configured.contains(&name.as_str()) {
+                return Err(anyhow::anyhow!("Unknown location: {}", name));
+            }
+        }
+        let selected = self.locations.as_ref().map_or(configured.len(), Vec::len);
+        match self.location_quorum {
+            Some(quorum) if quorum == 0 || quorum as usize > selected => Err(anyhow::anyhow!(
+                "location_quorum ({}) must be between 1 and the number of selected locations ({})",
+                quorum,
+                selected
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Configuration for detecting a monitor that keeps changing state
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct FlapDetectionSpec {
+    /// Number of state transitions within the window that marks the monitor as flapping. Must be at least 1.
+    pub max_transitions: u32,
+    /// Length of the window in seconds. Must be at least 1.
+    pub window: u32,
+}
+
+impl FlapDetectionSpec {
+    /// Rejects settings under which a monitor would always, or never, count as flapping
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_transitions == 0 {
+            return Err(anyhow::anyhow!(
+                "flap_detection.max_transitions must be at least 1"
+            ));
+        }
+        if self.window == 0 {
+            return Err(anyhow::anyhow!("flap_detection.window must be at least 1"));
+        }
+        Ok(())
+    }
 }
 
 /// Reference to a secret key
@@ -31,26 +94,210 @@ pub struct SecretKeySelector {
     pub key: String,
 }
 
+/// Reference to a ConfigMap key
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct ConfigMapKeySelector {
+    /// The name of the ConfigMap
+    pub name: String,
+    /// The key of the ConfigMap to select
+    pub key: String,
+}
+
 /// The current state of the monitor
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
 pub enum MonitorState {
     /// The target is reachable and healthy
     Healthy,
-    /// The target is reachable but showing signs of issues (not currently used)
+    /// The target is reachable but showing signs of issues (e.g. a certificate close to expiry)
     Warning,
     /// The target is unreachable
     Critical,
     /// No check has been performed yet
+    #[default]
     NoData,
+    /// The check itself could not be performed (e.g. bad configuration or a missing secret)
+    Error,
+}
+
+/// The outcome of a single check performed by a monitor
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct CheckResult {
+    /// The state the target is in
+    pub state: MonitorState,
+    /// A human-readable explanation of the state (e.g. "connection refused")
+    pub message: Option<String>,
+    /// How long the probe took in milliseconds
+    pub response_time_ms: Option<u64>,
+    /// The HTTP status code the target answered with, for HTTP checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+}
+
+impl CheckResult {
+    pub fn new(state: MonitorState, message: impl Into<String>) -> Self {
+        CheckResult {
+            state,
+            message: Some(message.into()),
+            response_time_ms: None,
+            status_code: None,
+        }
+    }
+
+    pub fn with_response_time(mut self, elapsed: std::time::Duration) -> Self {
+        self.response_time_ms = Some(elapsed.as_millis() as u64);
+        self
+    }
+
+    pub fn with_status_code(mut self, status_code: u16) -> Self {
+        self.status_code = Some(status_code);
+        self
+    }
+
+    /// Downgrades a Healthy result whose response time reached a threshold to Warning or Critical
+    pub fn with_latency_thresholds(
+        mut self,
+        warning_ms: Option<u64>,
+        critical_ms: Option<u64>,
+    ) -> Self {
+        let Some(response_time_ms) = self.response_time_ms else {
+            return self;
+        };
+        if self.state != MonitorState::Healthy {
+            return self;
+        }
+        let (state, threshold_ms) = match (critical_ms, warning_ms) {
+            (Some(critical_ms), _) if response_time_ms >= critical_ms => {
+                (MonitorState::Critical, critical_ms)
+            }
+            (_, Some(warning_ms)) if response_time_ms >= warning_ms => {
+                (MonitorState::Warning, warning_ms)
+            }
+            _ => return self,
+        };
+        let level = if state == MonitorState::Critical {
+            "critical"
+        } else {
+            "warning"
+        };
+        let slow = format!(
+            "{}ms reached the {} threshold of {}ms",
+            response_time_ms, level, threshold_ms
+        );
+        self.message = Some(match self.message.take() {
+            Some(message) => format!("{}, {}", message, slow),
+            None => slow,
+        });
+        self.state = state;
+        self
+    }
+}
+
+/// Checks that the warning latency threshold is below the critical one
+pub fn validate_latency_thresholds(
+    warning_ms: Option<u64>,
+    critical_ms: Option<u64>,
+) -> anyhow::Result<()> {
+    match (warning_ms, critical_ms) {
+        (Some(warning_ms), Some(critical_ms)) if warning_ms > critical_ms => Err(anyhow::anyhow!(
+            "warning_latency_ms ({}) must not be greater than critical_latency_ms ({})",
+            warning_ms,
+            critical_ms
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A standard Kubernetes-style condition on the monitor status
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct MonitorCondition {
+    /// The type of the condition (Ready or Degraded)
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The status of the condition: True, False or Unknown
+    pub status: String,
+    /// A CamelCase reason for the condition's last transition
+    pub reason: Option<String>,
+    /// A human-readable message with details about the transition
+    pub message: Option<String>,
+    /// The timestamp of the last status change of the condition in RFC3339 format
+    pub last_transition_time: Option<String>,
+}
+
+/// The result of the last check from a single worker location
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct LocationStatus {
+    /// The name of the location
+    pub name: String,
+    /// The state seen from this location
+    pub state: MonitorState,
+    /// A human-readable explanation of the result at this location
+    pub message: Option<String>,
+    /// How long the probe from this location took in milliseconds
+    pub response_time_ms: Option<u64>,
+}
+
+/// Condition set by the controller while the worker cannot be reached
+pub const WORKER_UNREACHABLE_CONDITION: &str = "WorkerUnreachable";
+
+/// Sets a condition, keeping its transition time if the status did not change
+pub fn set_condition(
+    conditions: &mut Vec<MonitorCondition>,
+    type_: &str,
+    status: &str,
+    reason: &str,
+    message: Option<&str>,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    let last_transition_time = conditions
+        .iter()
+        .find(|c| c.type_ == type_)
+        .filter(|c| c.status == status)
+        .and_then(|c| c.last_transition_time.clone())
+        .unwrap_or_else(|| now.to_rfc3339());
+    let condition = MonitorCondition {
+        type_: type_.to_string(),
+        status: status.to_string(),
+        reason: Some(reason.to_string()),
+        message: message.map(str::to_string),
+        last_transition_time: Some(last_transition_time),
+    };
+    match conditions.iter_mut().find(|c| c.type_ == type_) {
+        Some(existing) => *existing = condition,
+        None => conditions.push(condition),
+    }
 }
 
 /// The status of the monitor resource
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 pub struct MonitorStatus {
     /// The timestamp of the last check in RFC3339 format
     pub last_checked: Option<String>,
     /// The current state of the monitor
     pub state: MonitorState,
+    /// The timestamp of the last state change in RFC3339 format
+    pub last_transition_time: Option<String>,
+    /// A human-readable explanation of the last check result
+    pub message: Option<String>,
+    /// How long the last probe took in milliseconds
+    pub response_time_ms: Option<u64>,
+    /// The generation of the monitor spec the status was computed for
+    pub observed_generation: Option<i64>,
+    /// Ready, Degraded and WorkerUnreachable conditions
+    pub conditions: Option<Vec<MonitorCondition>>,
+    /// The number of attempts made during the last check
+    pub attempts: Option<u32>,
+    /// The number of consecutive failed checks
+    pub consecutive_failures: Option<u32>,
+    /// The number of consecutive successful checks
+    pub consecutive_successes: Option<u32>,
+    /// Timestamps in RFC3339 format of the state transitions within the flap detection window
+    pub recent_transitions: Option<Vec<String>>,
+    /// Whether the monitor is flapping. Notifications are suppressed while flapping.
+    pub flapping: Option<bool>,
+    /// The state last sent to notifiers
+    pub notified_state: Option<MonitorState>,
+    /// Per-location results when the monitor is probed from several locations
+    pub locations: Option<Vec<LocationStatus>>,
 }
 
 /* Helper functions */
@@ -97,15 +344,30 @@ pub trait ControllerResource:
 /// Trait for monitor resources to implement generic controller logic
 #[allow(async_fn_in_trait)]
 pub trait MonitorResource: ControllerResource {
-    /// Performs the check and returns the state
-    async fn check(&self) -> anyhow::Result<MonitorState>;
+    /// Performs the check and returns the result.
+    /// The client is used to read Secrets the check needs, and is not set on workers without Kubernetes credentials.
+    fn check(
+        &self,
+        client: Option<kube::Client>,
+    ) -> impl Future<Output = anyhow::Result<CheckResult>> + Send;
 
     /// Handles the HTTP request for the resource
-    async fn handle_http(state: State<AppState>, monitor: Json<Self>) -> StatusCode;
+    async fn handle_http(state: State<AppState>, monitor: Json<Self>) -> Response;
+
+    /// Returns a human-readable description of what is being checked (e.g. a URL or host:port)
+    fn target(&self) -> String;
+
+    /// Returns the host the check connects to, used to limit concurrent checks per host
+    fn target_host(&self) -> String;
 
     /// Returns the monitor configuration
     fn monitor_config(&self) -> &MonitorConfigSpec;
 
+    /// Whether the check reads Secrets or ConfigMaps, which workers without Kubernetes credentials cannot
+    fn reads_secrets(&self) -> bool {
+        false
+    }
+
     /// Returns the current status of the monitor
     fn status(&self) -> Option<&MonitorStatus>;
 }
@@ -0,0 +1,129 @@
+pub mod v1alpha1;
+
+use hickory_resolver::TokioAsyncResolver;
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::rr::{RData, RecordType};
+use std::net::{IpAddr, SocketAddr};
+
+/// Parses a nameserver given as "ip" or "ip:port" (IPv6 with a port must use "[ip]:port")
+pub fn parse_nameserver(nameserver: &str) -> anyhow::Result<SocketAddr> {
+    if let Ok(addr) = nameserver.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    nameserver
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|e| anyhow::anyhow!("Invalid nameserver \"{}\": {}", nameserver, e))
+}
+
+/// Resolves `name` and returns the answers of the requested record type as normalized strings.
+/// Uses the system resolver configuration when no nameserver is given.
+pub async fn resolve_records(
+    name: &str,
+    record_type: RecordType,
+    nameserver: Option<&str>,
+    timeout: std::time::Duration,
+) -> anyhow::Result<Vec<String>> {
+    let mut opts = ResolverOpts::default();
+    opts.timeout = timeout;
+    opts.attempts = 1;
+    // We want to see what the nameserver is serving right now
+    opts.cache_size = 0;
+
+    let resolver = match nameserver {
+        Some(ns) => {
+            let addr = parse_nameserver(ns)?;
+            let group = NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true);
+            TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, vec![], group), opts)
+        }
+        None => {
+            let (config, _) = hickory_resolver::system_conf::read_system_conf()?;
+            TokioAsyncResolver::tokio(config, opts)
+        }
+    };
+
+    let lookup = resolver.lookup(name, record_type).await?;
+
+    // A lookup may include the CNAME chain, so only keep the records we asked for
+    let answers = lookup
+        .iter()
+        .filter(|rdata| rdata.record_type() == record_type)
+        .filter_map(format_rdata)
+        .collect();
+
+    Ok(answers)
+}
+
+fn format_rdata(rdata: &RData) -> Option<String> {
+    let answer = match rdata {
+        RData::A(a) => a.to_string(),
+        RData::AAAA(aaaa) => aaaa.to_string(),
+        RData::CNAME(cname) => cname.to_string(),
+        RData::MX(mx) => format!("{} {}", mx.preference(), mx.exchange()),
+        RData::TXT(txt) => txt.to_string(),
+        RData::SRV(srv) => format!(
+            "{} {} {} {}",
+            srv.priority(),
+            srv.weight(),
+            srv.port(),
+            srv.target()
+        ),
+        _ => return None,
+    };
+    Some(normalize_answer(&answer))
+}
+
+/// Normalizes an answer for comparison: lowercase and without the trailing dot of FQDNs
+pub fn normalize_answer(answer: &str) -> String {
+    answer.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// Returns true when both lists contain the same set of answers, ignoring order and duplicates
+pub fn answers_match(actual: &[String], expected: &[String]) -> bool {
+    let actual: std::collections::BTreeSet<String> =
+        actual.iter().map(|a| normalize_answer(a)).collect();
+    let expected: std::collections::BTreeSet<String> =
+        expected.iter().map(|e| normalize_answer(e)).collect();
+    actual == expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nameserver() {
+        assert_eq!(
+            parse_nameserver("1.1.1.1").unwrap(),
+            "1.1.1.1:53".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_nameserver("10.0.0.10:5353").unwrap(),
+            "10.0.0.10:5353".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_nameserver("[::1]:53").unwrap(),
+            "[::1]:53".parse::<SocketAddr>().unwrap()
+        );
+        assert!(parse_nameserver("dns.example.com").is_err());
+    }
+
+    #[test]
+    fn test_answers_match() {
+        let actual = vec![
+            "10 mail.example.com.".to_string(),
+            "20 backup.example.com.".to_string(),
+        ];
+        assert!(answers_match(
+            &actual,
+            &[
+                "20 Backup.Example.com".to_string(),
+                "10 mail.example.com".to_string()
+            ]
+        ));
+        assert!(!answers_match(
+            &actual,
+            &["10 mail.example.com".to_string()]
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::shared::context::AppState;
+use crate::shared::context::Context;
+use crate::shared::resources::common::{
+    self, CheckResult, ControllerResource, MonitorConfigSpec, MonitorState, MonitorStatus,
+};
+use crate::shared::resources::monitors::dns_monitor::{
+    answers_match, parse_nameserver, resolve_records,
+};
+use crate::shared::resources::worker;
+use axum::{
+    extract::{Json, State},
+    response::Response,
+};
+use hickory_resolver::proto::rr::RecordType as DnsRecordType;
+use kube::{CustomResource, ResourceExt, runtime::controller::Action};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum RecordType {
+    A,
+    AAAA,
+    CNAME,
+    MX,
+    TXT,
+    SRV,
+}
+
+impl From<&RecordType> for DnsRecordType {
+    fn from(record_type: &RecordType) -> Self {
+        match record_type {
+            RecordType::A => DnsRecordType::A,
+            RecordType::AAAA => DnsRecordType::AAAA,
+            RecordType::CNAME => DnsRecordType::CNAME,
+            RecordType::MX => DnsRecordType::MX,
+            RecordType::TXT => DnsRecordType::TXT,
+            RecordType::SRV => DnsRecordType::SRV,
+        }
+    }
+}
+
+/// Specification for the DNSMonitor resource
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "kastlewatch.io",
+    version = "v1alpha1",
+    kind = "DNSMonitor",
+    namespaced
+)]
+#[kube(status = "MonitorStatus")]
+pub struct DNSMonitorSpec {
+    /// The name to resolve
+    pub name: String,
+    /// The record type to query: A, AAAA, CNAME, MX, TXT or SRV
+    pub record_type: RecordType,
+    /// The nameserver to query as "ip" or "ip:port". Optional. If not defined, use the system resolver.
+    pub nameserver: Option<String>,
+    /// The expected answers. Optional. If defined, the answer set must match exactly (order and case are ignored).
+    /// MX answers are written as "preference exchange", SRV answers as "priority weight port target".
+    pub expected: Option<Vec<String>>,
+    /// Configuration for the monitoring behavior
+    pub monitor_config: MonitorConfigSpec,
+}
+
+impl ControllerResource for DNSMonitor {
+    fn success_policy(&self) -> Action {
+        Action::requeue(Duration::from_secs(
+            self.spec.monitor_config.polling_frequency as u64,
+        ))
+    }
+
+    fn error_policy(&self, error: &anyhow::Error, _ctx: Arc<Context>) -> Action {
+        let name = self.name_any();
+        error!("Reconciliation error for \"{}\": {:?}", name, error);
+        Action::requeue(Duration::from_secs(5))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.spec.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("Name to resolve must not be empty"));
+        }
+        if let Some(nameserver) = &self.spec.nameserver {
+            parse_nameserver(nameserver)?;
+        }
+        Ok(())
+    }
+}
+
+impl common::MonitorResource for DNSMonitor {
+    async fn check(&self, _client: Option<kube::Client>) -> anyhow::Result<CheckResult> {
+        let name = &self.spec.name;
+        let record_type = &self.spec.record_type;
+        info!("Resolving {} {:?}", name, record_type);
+
+        // A bad nameserver is a broken monitor, not a broken record
+        if let Some(nameserver) = &self.spec.nameserver {
+            parse_nameserver(nameserver)?;
+        }
+
+        let timeout = Duration::from_secs(self.spec.monitor_config.timeout as u64);
+        let start = std::time::Instant::now();
+        let result = resolve_records(
+            name,
+            record_type.into(),
+            self.spec.nameserver.as_deref(),
+            timeout,
+        )
+        .await;
+        let elapsed = start.elapsed();
+
+        let result = match result {
+            Ok(answers) => match &self.spec.expected {
+                Some(expected) if !answers_match(&answers, expected) => CheckResult::new(
+                    MonitorState::Critical,
+                    format!("answers {:?} do not match expected {:?}", answers, expected),
+                ),
+                _ if answers.is_empty() => CheckResult::new(
+                    MonitorState::Critical,
+                    format!("no {:?} records for {}", record_type, name),
+                ),
+                _ => CheckResult::new(MonitorState::Healthy, format!("answers {:?}", answers)),
+            }
+            .with_response_time(elapsed),
+            Err(e) => {
+                info!("Resolution failed: {:?}", e);
+                CheckResult::new(MonitorState::Critical, format!("resolution failed: {}", e))
+            }
+        };
+
+        info!("Check complete: {:?} ({:?})", result.state, result.message);
+        Ok(result)
+    }
+
+    async fn handle_http(State(state): State<AppState>, Json(monitor): Json<Self>) -> Response {
+        worker::enqueue(state, monitor)
+    }
+
+    fn target(&self) -> String {
+        format!("{} {:?}", self.spec.name, self.spec.record_type)
+    }
+
+    fn target_host(&self) -> String {
+        self.spec
+            .nameserver
+            .clone()
+            .unwrap_or_else(|| "system-resolver".to_string())
+    }
+
+    fn monitor_config(&self) -> &MonitorConfigSpec {
+        &self.spec.monitor_config
+    }
+
+    fn status(&self) -> Option<&MonitorStatus> {
+        self.status.as_ref()
+    }
+}
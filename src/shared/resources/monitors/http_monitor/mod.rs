@@ -1 +1,86 @@
+use crate::shared::resources::common::SecretKeySelector;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::CustomResourceExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 pub mod v1alpha1;
+pub mod v1alpha2;
+
+/// The version objects are stored in. Older versions are still served and converted on read.
+pub const STORAGE_VERSION: &str = "v1alpha2";
+
+/// A header sent with the request
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct HTTPHeader {
+    /// The header name
+    pub name: String,
+    /// The header value. Exactly one of value and value_secret_ref must be set.
+    pub value: Option<String>,
+    /// Reference to the secret containing the header value. Redirects to another host are not followed when set.
+    pub value_secret_ref: Option<SecretKeySelector>,
+}
+
+/// HTTP basic authentication credentials
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct BasicAuth {
+    /// The username
+    pub username: String,
+    /// Reference to the secret containing the password
+    pub password_secret_ref: SecretKeySelector,
+}
+
+/// A bearer token sent in the Authorization header
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct BearerToken {
+    /// Reference to the secret containing the token
+    pub token_secret_ref: SecretKeySelector,
+}
+
+/// The HTTPMonitor CRD with every version served.
+/// There is no conversion webhook, the API server only rewrites apiVersion, so v1alpha1 is read-compatible only:
+/// clients reading an object as v1alpha1 ignore the v1alpha2 fields, and writing it back as v1alpha1 prunes them.
+/// Once an object uses v1alpha2 fields, it must only be written as v1alpha2.
+pub fn crd() -> CustomResourceDefinition {
+    kube::core::crd::merge_crds(
+        vec![v1alpha1::HTTPMonitor::crd(), v1alpha2::HTTPMonitor::crd()],
+        STORAGE_VERSION,
+    )
+    .expect("HTTPMonitor versions share group, kind and scope")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crd_serves_all_versions() {
+        let crd = crd();
+        let mut versions: Vec<(&str, bool, bool)> = crd
+            .spec
+            .versions
+            .iter()
+            .map(|v| (v.name.as_str(), v.served, v.storage))
+            .collect();
+        versions.sort();
+        assert_eq!(
+            versions,
+            vec![("v1alpha1", true, false), ("v1alpha2", true, true)]
+        );
+
+        // json_body holds arbitrary JSON but stays optional
+        let spec = serde_json::to_value(&v1alpha2::HTTPMonitor::crd().spec.versions[0].schema)
+            .unwrap()["openAPIV3Schema"]["properties"]["spec"]
+            .clone();
+        assert_eq!(
+            spec["properties"]["json_body"]["x-kubernetes-preserve-unknown-fields"],
+            true
+        );
+        assert!(
+            !spec["required"]
+                .as_array()
+                .unwrap()
+                .contains(&serde_json::json!("json_body"))
+        );
+    }
+}
@@ -1,29 +1,41 @@
+use super::v1alpha2;
 use crate::shared::context::AppState;
 use crate::shared::context::Context;
 use crate::shared::resources::common::{
-    self, ControllerResource, MonitorConfigSpec, MonitorState, MonitorStatus,
+    self, CheckResult, ControllerResource, MonitorConfigSpec, MonitorStatus,
 };
 use crate::shared::resources::worker;
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    response::Response,
 };
-use base64::prelude::*;
-use kube::{CustomResource, ResourceExt, runtime::controller::Action};
+use kube::{Client, CustomResource, ResourceExt, runtime::controller::Action};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::time::Duration;
-use tracing::{error, info};
+use tracing::error;
 
-#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub use super::{BasicAuth, BearerToken, HTTPHeader};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema, PartialEq)]
 pub enum Method {
+    #[default]
     GET,
     POST,
 }
 
+impl From<Method> for v1alpha2::Method {
+    fn from(method: Method) -> Self {
+        match method {
+            Method::GET => v1alpha2::Method::GET,
+            Method::POST => v1alpha2::Method::POST,
+        }
+    }
+}
+
 /// Specification for the HTTPMonitor resource
-#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 #[kube(
     group = "kastlewatch.io",
     version = "v1alpha1",
@@ -42,6 +54,34 @@ pub struct HTTPMonitorSpec {
     pub status_code: Option<Vec<u16>>,
     /// A base64 string of data to use as the body when method is POST. Optional. Must be base64 encoded.
     pub base64_data: Option<String>,
+    /// Headers to send with the request. Optional.
+    pub headers: Option<Vec<HTTPHeader>>,
+    /// Basic authentication credentials. Optional. Cannot be combined with bearer_token.
+    pub basic_auth: Option<BasicAuth>,
+    /// Bearer token authentication. Optional. Cannot be combined with basic_auth.
+    pub bearer_token: Option<BearerToken>,
+}
+
+/// v1alpha2 only adds fields and methods, so every v1alpha1 object has an equivalent there
+impl From<HTTPMonitor> for v1alpha2::HTTPMonitor {
+    fn from(monitor: HTTPMonitor) -> Self {
+        let spec = monitor.spec;
+        v1alpha2::HTTPMonitor {
+            metadata: monitor.metadata,
+            spec: v1alpha2::HTTPMonitorSpec {
+                url: spec.url,
+                monitor_config: spec.monitor_config,
+                method: spec.method.into(),
+                status_code: spec.status_code,
+                base64_data: spec.base64_data,
+                headers: spec.headers,
+                basic_auth: spec.basic_auth,
+                bearer_token: spec.bearer_token,
+                ..Default::default()
+            },
+            status: monitor.status,
+        }
+    }
 }
 
 impl ControllerResource for HTTPMonitor {
@@ -58,80 +98,66 @@ impl ControllerResource for HTTPMonitor {
     }
 
     fn validate(&self) -> anyhow::Result<()> {
-        if let Some(data) = &self.spec.base64_data {
-            BASE64_STANDARD
-                .decode(data)
-                .map_err(|e| anyhow::anyhow!("Invalid base64 data: {}", e))?;
-        }
-        Ok(())
+        v1alpha2::HTTPMonitor::from(self.clone()).validate()
     }
 }
 
 impl common::MonitorResource for HTTPMonitor {
-    async fn check(&self) -> anyhow::Result<MonitorState> {
-        let url = &self.spec.url;
-        info!("Checking {}", url);
-
-        let timeout = Duration::from_secs(self.spec.monitor_config.timeout as u64);
-        let http_client = reqwest::Client::builder()
-            .timeout(timeout)
-            .build()
-            .unwrap_or_default();
-
-        let mut req_builder = match self.spec.method {
-            Method::GET => http_client.get(url),
-            Method::POST => http_client.post(url),
-        };
-
-        if let Some(base64_data) = &self.spec.base64_data {
-            if let Ok(decoded) = BASE64_STANDARD.decode(base64_data) {
-                req_builder = req_builder.body(decoded);
-            } else {
-                error!("Failed to decode base64 data for check, skipping body");
-            }
-        }
+    async fn check(&self, client: Option<Client>) -> anyhow::Result<CheckResult> {
+        common::MonitorResource::check(&v1alpha2::HTTPMonitor::from(self.clone()), client).await
+    }
 
-        let result = req_builder.send().await;
+    async fn handle_http(State(state): State<AppState>, Json(monitor): Json<Self>) -> Response {
+        worker::enqueue(state, monitor)
+    }
 
-        let is_healthy = match result {
-            Ok(response) => {
-                let status = response.status().as_u16();
-                if let Some(allowed_codes) = &self.spec.status_code {
-                    allowed_codes.contains(&status)
-                } else {
-                    status >= 200 && status < 300
-                }
-            }
-            Err(e) => {
-                info!("Check failed: {:?}", e);
-                false
-            }
-        };
-
-        let new_state = if is_healthy {
-            MonitorState::Healthy
-        } else {
-            MonitorState::Critical
-        };
-        info!("Check complete: {:?} (Healthy: {})", new_state, is_healthy);
-        Ok(new_state)
+    fn target(&self) -> String {
+        self.spec.url.clone()
     }
 
-    async fn handle_http(
-        State(state): State<AppState>,
-        Json(monitor): Json<HTTPMonitor>,
-    ) -> StatusCode {
-        tokio::spawn(async move {
-            worker::generic_worker_handler(monitor, state.client).await;
-        });
-        StatusCode::OK
+    fn target_host(&self) -> String {
+        reqwest::Url::parse(&self.spec.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| self.spec.url.clone())
     }
 
     fn monitor_config(&self) -> &MonitorConfigSpec {
         &self.spec.monitor_config
     }
 
+    fn reads_secrets(&self) -> bool {
+        common::MonitorResource::reads_secrets(&v1alpha2::HTTPMonitor::from(self.clone()))
+    }
+
     fn status(&self) -> Option<&MonitorStatus> {
         self.status.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_to_v1alpha2() {
+        let monitor: HTTPMonitor = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kastlewatch.io/v1alpha1",
+            "kind": "HTTPMonitor",
+            "metadata": { "name": "api", "namespace": "prod" },
+            "spec": {
+                "url": "http://api/health",
+                "method": "POST",
+                "base64_data": "cGluZw==",
+                "monitor_config": { "timeout": 5, "retries": 0, "polling_frequency": 60 }
+            }
+        }))
+        .unwrap();
+
+        let converted = v1alpha2::HTTPMonitor::from(monitor);
+        assert_eq!(converted.namespace().as_deref(), Some("prod"));
+        assert_eq!(converted.spec.method, v1alpha2::Method::POST);
+        assert_eq!(converted.spec.base64_data.as_deref(), Some("cGluZw=="));
+        assert!(converted.spec.body.is_none());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod dns_monitor;
 pub mod http_monitor;
 pub mod tcp_monitor;
+pub mod tls_certificate_monitor;
@@ -1,10 +1,19 @@
 pub mod v1alpha1;
 
-pub async fn check_tcp_connection(host: &str, port: u16, timeout: std::time::Duration) -> bool {
+/// Opens a TCP connection and returns how long it took, or why it failed
+pub async fn check_tcp_connection(
+    host: &str,
+    port: u16,
+    timeout: std::time::Duration,
+) -> anyhow::Result<std::time::Duration> {
     let addr = format!("{}:{}", host, port);
+    let start = std::time::Instant::now();
     match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(&addr)).await {
-        Ok(Ok(_)) => true,
-        Ok(Err(_)) => false,
-        Err(_) => false,
+        Ok(Ok(_)) => Ok(start.elapsed()),
+        Ok(Err(e)) => Err(anyhow::anyhow!("{}", e)),
+        Err(_) => Err(anyhow::anyhow!(
+            "connection timed out after {}s",
+            timeout.as_secs()
+        )),
     }
 }
@@ -1,13 +1,13 @@
 use crate::shared::context::AppState;
 use crate::shared::context::Context;
 use crate::shared::resources::common::{
-    self, ControllerResource, MonitorConfigSpec, MonitorState, MonitorStatus,
+    self, CheckResult, ControllerResource, MonitorConfigSpec, MonitorState, MonitorStatus,
 };
 use crate::shared::resources::monitors::tcp_monitor::check_tcp_connection;
 use crate::shared::resources::worker;
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    response::Response,
 };
 use kube::{CustomResource, ResourceExt, runtime::controller::Action};
 use schemars::JsonSchema;
@@ -17,7 +17,7 @@ use tokio::time::Duration;
 use tracing::{error, info};
 
 /// Specification for the TCPMonitor resource
-#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 #[kube(
     group = "kastlewatch.io",
     version = "v1alpha1",
@@ -30,6 +30,10 @@ pub struct TCPMonitorSpec {
     pub host: String,
     /// The port number to check
     pub port: u16,
+    /// Go to Warning when connecting takes at least this many milliseconds. Optional.
+    pub warning_latency_ms: Option<u64>,
+    /// Go to Critical when connecting takes at least this many milliseconds. Optional.
+    pub critical_latency_ms: Option<u64>,
     /// Configuration for the monitoring behavior
     pub monitor_config: MonitorConfigSpec,
 }
@@ -46,31 +50,45 @@ impl ControllerResource for TCPMonitor {
         error!("Reconciliation error for \"{}\": {:?}", name, error);
         Action::requeue(Duration::from_secs(5))
     }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        common::validate_latency_thresholds(
+            self.spec.warning_latency_ms,
+            self.spec.critical_latency_ms,
+        )
+    }
 }
 
 impl common::MonitorResource for TCPMonitor {
-    async fn check(&self) -> anyhow::Result<MonitorState> {
+    async fn check(&self, _client: Option<kube::Client>) -> anyhow::Result<CheckResult> {
         let host = &self.spec.host;
         let port = self.spec.port;
         info!("Checking {}:{}", host, port);
 
         let timeout = Duration::from_secs(self.spec.monitor_config.timeout as u64);
-        let is_open = check_tcp_connection(host, port, timeout).await;
-
-        let new_state = if is_open {
-            MonitorState::Healthy
-        } else {
-            MonitorState::Critical
+        let result = match check_tcp_connection(host, port, timeout).await {
+            Ok(elapsed) => CheckResult::new(
+                MonitorState::Healthy,
+                format!("connected to {}:{}", host, port),
+            )
+            .with_response_time(elapsed)
+            .with_latency_thresholds(self.spec.warning_latency_ms, self.spec.critical_latency_ms),
+            Err(e) => CheckResult::new(MonitorState::Critical, e.to_string()),
         };
-        info!("Check complete: {:?} (Open: {})", new_state, is_open);
-        Ok(new_state)
+        info!("Check complete: {:?} ({:?})", result.state, result.message);
+        Ok(result)
+    }
+
+    async fn handle_http(State(state): State<AppState>, Json(monitor): Json<Self>) -> Response {
+        worker::enqueue(state, monitor)
+    }
+
+    fn target(&self) -> String {
+        format!("{}:{}", self.spec.host, self.spec.port)
     }
 
-    async fn handle_http(State(state): State<AppState>, Json(monitor): Json<Self>) -> StatusCode {
-        tokio::spawn(async move {
-            worker::generic_worker_handler(monitor, state.client).await;
-        });
-        StatusCode::OK
+    fn target_host(&self) -> String {
+        self.spec.host.clone()
     }
 
     fn monitor_config(&self) -> &MonitorConfigSpec {
@@ -0,0 +1,191 @@
+pub mod v1alpha1;
+
+use openssl::asn1::Asn1Time;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl_probe::ProbeResult;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+/// What a TLS handshake told us about the certificate chain presented by a target
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateInfo {
+    /// Whole days until the first certificate in the chain expires (negative once expired)
+    pub days_remaining: i32,
+    /// The first verification error (untrusted, expired, hostname mismatch, ...), if any
+    pub verify_error: Option<String>,
+}
+
+/// Performs a TLS handshake against `host:port` and inspects the presented certificate chain.
+/// `server_name` overrides the name used for SNI and hostname verification, defaulting to `host`.
+pub async fn inspect_certificate(
+    host: &str,
+    port: u16,
+    server_name: Option<&str>,
+    timeout: std::time::Duration,
+) -> anyhow::Result<CertificateInfo> {
+    let host = host.to_string();
+    let server_name = server_name.unwrap_or(&host).to_string();
+
+    // openssl is blocking, keep it off the async runtime threads
+    tokio::task::spawn_blocking(move || {
+        let trust = openssl_probe::probe();
+        inspect_certificate_blocking(&host, port, &server_name, timeout, &trust)
+    })
+    .await?
+}
+
+fn inspect_certificate_blocking(
+    host: &str,
+    port: u16,
+    server_name: &str,
+    timeout: std::time::Duration,
+    trust: &ProbeResult,
+) -> anyhow::Result<CertificateInfo> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve {}:{}", host, port))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    // Record verification failures instead of aborting the handshake,
+    // so we can still read the chain and report why it is not trusted.
+    let verify_error: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let callback_error = verify_error.clone();
+
+    // The vendored OpenSSL only looks for roots under its own build prefix,
+    // which does not exist in the image, so point it at the system trust store.
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    if let Some(file) = &trust.cert_file {
+        builder.set_ca_file(file)?;
+    }
+    if let Some(dir) = &trust.cert_dir {
+        builder.load_verify_locations(None, Some(dir))?;
+    }
+    let connector = builder.build();
+    let mut config = connector.configure()?.verify_hostname(true);
+    config.set_verify_callback(SslVerifyMode::PEER, move |preverify_ok, ctx| {
+        if !preverify_ok {
+            let mut error = callback_error.lock().unwrap();
+            if error.is_none() {
+                *error = Some(ctx.error().error_string().to_string());
+            }
+        }
+        true
+    });
+
+    let tls_stream = config
+        .connect(server_name, stream)
+        .map_err(|e| anyhow::anyhow!("TLS handshake failed: {}", e))?;
+
+    let now = Asn1Time::days_from_now(0)?;
+    let ssl = tls_stream.ssl();
+    let mut days_remaining: Option<i32> = None;
+    if let Some(chain) = ssl.peer_cert_chain() {
+        for cert in chain {
+            let diff = now.diff(cert.not_after())?;
+            // TimeDiff splits the difference into days and seconds with the same sign
+            let days = if diff.secs < 0 {
+                diff.days - 1
+            } else {
+                diff.days
+            };
+            days_remaining = Some(days_remaining.map_or(days, |d| d.min(days)));
+        }
+    }
+    let days_remaining = days_remaining
+        .ok_or_else(|| anyhow::anyhow!("No certificate presented by {}:{}", host, port))?;
+
+    let verify_error = verify_error.lock().unwrap().take();
+    Ok(CertificateInfo {
+        days_remaining,
+        verify_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::ssl::SslAcceptor;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509, X509Name};
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Self-signed certificate for localhost, which doubles as its own CA
+    fn self_signed(key: &PKey<Private>) -> X509 {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    /// Completes `handshakes` TLS handshakes on localhost with the given certificate
+    fn serve(key: PKey<Private>, cert: X509, handshakes: usize) -> u16 {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(handshakes) {
+                let _ = acceptor.accept(stream.unwrap());
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_inspect_certificate_trust() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let cert = self_signed(&key);
+        let mut ca_file = tempfile::NamedTempFile::new().unwrap();
+        ca_file.write_all(&cert.to_pem().unwrap()).unwrap();
+        let port = serve(key, cert, 2);
+        let timeout = Duration::from_secs(5);
+
+        let trusted = ProbeResult {
+            cert_file: Some(ca_file.path().to_path_buf()),
+            cert_dir: None,
+        };
+        let info = inspect_certificate_blocking("127.0.0.1", port, "localhost", timeout, &trusted)
+            .unwrap();
+        assert_eq!(info.verify_error, None);
+        assert!((29..=30).contains(&info.days_remaining));
+
+        let untrusted = ProbeResult {
+            cert_file: None,
+            cert_dir: None,
+        };
+        let info =
+            inspect_certificate_blocking("127.0.0.1", port, "localhost", timeout, &untrusted)
+                .unwrap();
+        assert!(info.verify_error.is_some());
+    }
+}
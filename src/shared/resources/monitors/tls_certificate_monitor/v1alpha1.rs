@@ -0,0 +1,206 @@
+use crate::shared::context::AppState;
+use crate::shared::context::Context;
+use crate::shared::resources::common::{
+    self, CheckResult, ControllerResource, MonitorConfigSpec, MonitorState, MonitorStatus,
+};
+use crate::shared::resources::monitors::tls_certificate_monitor::{
+    CertificateInfo, inspect_certificate,
+};
+use crate::shared::resources::worker;
+use axum::{
+    extract::{Json, State},
+    response::Response,
+};
+use kube::{CustomResource, ResourceExt, runtime::controller::Action};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info};
+
+const DEFAULT_WARNING_DAYS: u32 = 30;
+const DEFAULT_CRITICAL_DAYS: u32 = 0;
+
+/// Specification for the TLSCertificateMonitor resource
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "kastlewatch.io",
+    version = "v1alpha1",
+    kind = "TLSCertificateMonitor",
+    namespaced
+)]
+#[kube(status = "MonitorStatus")]
+pub struct TLSCertificateMonitorSpec {
+    /// The hostname or IP address of the target
+    pub host: String,
+    /// The port number to connect to
+    pub port: u16,
+    /// The name to send as SNI and to verify the certificate against. Optional. If not defined, use the host.
+    pub server_name: Option<String>,
+    /// Go to Warning when the certificate expires within this many days. Optional. Defaults to 30.
+    pub warning_days: Option<u32>,
+    /// Go to Critical when the certificate expires within this many days. Optional. Defaults to 0 (expired only).
+    pub critical_days: Option<u32>,
+    /// Configuration for the monitoring behavior
+    pub monitor_config: MonitorConfigSpec,
+}
+
+impl TLSCertificateMonitor {
+    /// Maps the inspected certificate chain to a monitor state using the configured thresholds
+    pub fn state_for(&self, info: &CertificateInfo) -> MonitorState {
+        let warning_days = self.spec.warning_days.unwrap_or(DEFAULT_WARNING_DAYS) as i64;
+        let critical_days = self.spec.critical_days.unwrap_or(DEFAULT_CRITICAL_DAYS) as i64;
+        let days_remaining = info.days_remaining as i64;
+
+        if info.verify_error.is_some() || days_remaining < 0 || days_remaining < critical_days {
+            MonitorState::Critical
+        } else if days_remaining < warning_days {
+            MonitorState::Warning
+        } else {
+            MonitorState::Healthy
+        }
+    }
+}
+
+impl ControllerResource for TLSCertificateMonitor {
+    fn success_policy(&self) -> Action {
+        Action::requeue(Duration::from_secs(
+            self.spec.monitor_config.polling_frequency as u64,
+        ))
+    }
+
+    fn error_policy(&self, error: &anyhow::Error, _ctx: Arc<Context>) -> Action {
+        let name = self.name_any();
+        error!("Reconciliation error for \"{}\": {:?}", name, error);
+        Action::requeue(Duration::from_secs(5))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let warning_days = self.spec.warning_days.unwrap_or(DEFAULT_WARNING_DAYS);
+        let critical_days = self.spec.critical_days.unwrap_or(DEFAULT_CRITICAL_DAYS);
+        if critical_days > warning_days {
+            return Err(anyhow::anyhow!(
+                "critical_days ({}) must not be greater than warning_days ({})",
+                critical_days,
+                warning_days
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl common::MonitorResource for TLSCertificateMonitor {
+    async fn check(&self, _client: Option<kube::Client>) -> anyhow::Result<CheckResult> {
+        let host = &self.spec.host;
+        let port = self.spec.port;
+        info!("Checking certificate of {}:{}", host, port);
+
+        let timeout = Duration::from_secs(self.spec.monitor_config.timeout as u64);
+        let start = std::time::Instant::now();
+        let result =
+            inspect_certificate(host, port, self.spec.server_name.as_deref(), timeout).await;
+        let elapsed = start.elapsed();
+
+        let result = match result {
+            Ok(info) => {
+                let message = match &info.verify_error {
+                    Some(verify_error) => {
+                        format!("certificate verification failed: {}", verify_error)
+                    }
+                    None if info.days_remaining < 0 => {
+                        format!("certificate expired {} days ago", -info.days_remaining)
+                    }
+                    None => format!("certificate expires in {} days", info.days_remaining),
+                };
+                CheckResult::new(self.state_for(&info), message).with_response_time(elapsed)
+            }
+            Err(e) => {
+                info!("Check failed: {:?}", e);
+                CheckResult::new(MonitorState::Critical, e.to_string())
+            }
+        };
+
+        info!("Check complete: {:?} ({:?})", result.state, result.message);
+        Ok(result)
+    }
+
+    async fn handle_http(State(state): State<AppState>, Json(monitor): Json<Self>) -> Response {
+        worker::enqueue(state, monitor)
+    }
+
+    fn target(&self) -> String {
+        format!("{}:{}", self.spec.host, self.spec.port)
+    }
+
+    fn target_host(&self) -> String {
+        self.spec.host.clone()
+    }
+
+    fn monitor_config(&self) -> &MonitorConfigSpec {
+        &self.spec.monitor_config
+    }
+
+    fn status(&self) -> Option<&MonitorStatus> {
+        self.status.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(warning_days: Option<u32>, critical_days: Option<u32>) -> TLSCertificateMonitor {
+        TLSCertificateMonitor::new(
+            "test-monitor",
+            TLSCertificateMonitorSpec {
+                host: "example.com".to_string(),
+                port: 443,
+                server_name: None,
+                warning_days,
+                critical_days,
+                monitor_config: MonitorConfigSpec {
+                    timeout: 5,
+                    retries: 3,
+                    polling_frequency: 10,
+                    notifiers_match_labels: None,
+                    ..Default::default()
+                },
+            },
+        )
+    }
+
+    fn info(days_remaining: i32, verify_error: Option<&str>) -> CertificateInfo {
+        CertificateInfo {
+            days_remaining,
+            verify_error: verify_error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_state_for_thresholds() {
+        let m = monitor(None, None);
+        assert_eq!(m.state_for(&info(90, None)), MonitorState::Healthy);
+        assert_eq!(m.state_for(&info(10, None)), MonitorState::Warning);
+        assert_eq!(m.state_for(&info(-1, None)), MonitorState::Critical);
+
+        let m = monitor(Some(14), Some(3));
+        assert_eq!(m.state_for(&info(14, None)), MonitorState::Healthy);
+        assert_eq!(m.state_for(&info(5, None)), MonitorState::Warning);
+        assert_eq!(m.state_for(&info(2, None)), MonitorState::Critical);
+    }
+
+    #[test]
+    fn test_state_for_verify_error() {
+        let m = monitor(None, None);
+        assert_eq!(
+            m.state_for(&info(300, Some("hostname mismatch"))),
+            MonitorState::Critical
+        );
+    }
+
+    #[test]
+    fn test_validate_thresholds() {
+        assert!(monitor(Some(7), Some(14)).validate().is_err());
+        assert!(monitor(Some(14), Some(7)).validate().is_ok());
+    }
+}
@@ -1,13 +1,68 @@
 use crate::shared::context::Context;
 use crate::shared::resources::common::{ControllerResource, MonitorState, SecretKeySelector};
-use crate::shared::resources::notifiers::{self, NotifierResource};
+use crate::shared::resources::notifiers::{self, Notification, NotifierResource, template};
 use kube::{Client, CustomResource, ResourceExt, runtime::controller::Action};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::time::Duration;
 use tracing::error;
 
+const DEFAULT_TITLE: &str = "Monitor {{monitor_name}} is {{new_state}}";
+const DEFAULT_DESCRIPTION: &str = "State changed from {{old_state}} to {{new_state}}";
+const STATES: &[&str] = &["Healthy", "Warning", "Critical", "NoData", "Error"];
+/// Discord rejects embeds with empty or longer texts
+const TITLE_LIMIT: usize = 256;
+const DESCRIPTION_LIMIT: usize = 4096;
+const FIELD_NAME_LIMIT: usize = 256;
+const FIELD_VALUE_LIMIT: usize = 1024;
+/// Stands in for a template that rendered empty, such as {{message}} without a message
+const EMPTY_PLACEHOLDER: &str = "-";
+
+/// Fits a rendered embed text within Discord's limits, truncating it with an ellipsis
+fn embed_text(text: String, limit: usize) -> String {
+    if text.trim().is_empty() {
+        return EMPTY_PLACEHOLDER.to_string();
+    }
+    if text.chars().count() <= limit {
+        return text;
+    }
+    let mut truncated: String = text.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// An embed field, whose name and value are templates
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct DiscordEmbedField {
+    /// The field name
+    pub name: String,
+    /// The field value
+    pub value: String,
+    /// Whether the field is displayed inline. Optional. Defaults to false.
+    pub inline: Option<bool>,
+}
+
+/// Templates controlling the Discord message. Placeholders: {{monitor_name}}, {{monitor_namespace}},
+/// {{monitor_kind}}, {{target}}, {{old_state}}, {{new_state}}, {{message}}, {{response_time_ms}} and {{timestamp}}.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct DiscordMessageFormat {
+    /// The embed title. Optional. Defaults to "Monitor {{monitor_name}} is {{new_state}}".
+    pub title: Option<String>,
+    /// The embed description. Optional. Defaults to "State changed from {{old_state}} to {{new_state}}".
+    pub description: Option<String>,
+    /// Overrides the webhook's username. Optional.
+    pub username: Option<String>,
+    /// Overrides the webhook's avatar. Optional.
+    pub avatar_url: Option<String>,
+    /// Embed fields. Optional.
+    pub fields: Option<Vec<DiscordEmbedField>>,
+    /// Message content per new state, typically mentions such as "<@&role-id>". Optional.
+    /// Keys are Healthy, Warning, Critical, NoData or Error.
+    pub mentions: Option<BTreeMap<String, String>>,
+}
+
 /// Specification for the DiscordNotifier resource
 #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
 #[kube(
@@ -19,8 +74,10 @@ use tracing::error;
 pub struct DiscordNotifierSpec {
     /// Reference to the secret containing the webhook URL
     pub webhook_secret_ref: SecretKeySelector,
-    /// Optional message format (not currently used, but good for future proofing)
+    /// Deprecated, use format.title. A template for the embed title. Optional.
     pub message_format: Option<String>,
+    /// Optional message format. If not defined, send the default title and description.
+    pub format: Option<DiscordMessageFormat>,
 }
 
 impl ControllerResource for DiscordNotifier {
@@ -35,54 +92,126 @@ impl ControllerResource for DiscordNotifier {
         error!("Reconciliation error for \"{}\": {:?}", name, error);
         Action::requeue(Duration::from_secs(60))
     }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(format) = &self.spec.format {
+            if format.title.is_some() && self.spec.message_format.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Only one of message_format and format.title can be set"
+                ));
+            }
+            for (state, _) in format.mentions.iter().flatten() {
+                if !STATES.contains(&state.as_str()) {
+                    return Err(anyhow::anyhow!(
+                        "Unknown state \"{}\" in mentions, expected one of {:?}",
+                        state,
+                        STATES
+                    ));
+                }
+            }
+        }
+        // Rendering exercises every template, so broken ones surface at reconcile time
+        for sample in Notification::samples() {
+            self.build_payload(&sample)?;
+        }
+        Ok(())
+    }
 }
 
 impl NotifierResource for DiscordNotifier {
-    async fn notify(
-        &self,
-        client: Client,
-        monitor_name: &str,
-        old_state: &MonitorState,
-        new_state: &MonitorState,
-    ) -> anyhow::Result<()> {
+    async fn notify(&self, client: Client, notification: &Notification) -> anyhow::Result<()> {
         // Get webhook URL
         let ns = self.namespace().unwrap_or_else(|| "default".to_string());
         let webhook_url =
             notifiers::get_secret_value(client, &ns, &self.spec.webhook_secret_ref).await?;
 
-        self.send_discord_notification(&webhook_url, monitor_name, old_state, new_state)
+        self.send_discord_notification(&webhook_url, notification)
             .await
     }
 }
 
 impl DiscordNotifier {
-    async fn send_discord_notification(
-        &self,
-        webhook_url: &str,
-        monitor_name: &str,
-        old_state: &MonitorState,
-        new_state: &MonitorState,
-    ) -> anyhow::Result<()> {
-        // Build Discord payload
-        let color = match new_state {
+    fn build_payload(&self, notification: &Notification) -> anyhow::Result<serde_json::Value> {
+        let color = match notification.new_state {
             MonitorState::Healthy => 0x00FF00,  // Green
             MonitorState::Warning => 0xFFFF00,  // Yellow
             MonitorState::Critical => 0xFF0000, // Red
             MonitorState::NoData => 0x808080,   // Gray
+            MonitorState::Error => 0x800080,    // Purple
         };
 
-        let title = format!("Monitor {} is {:?}", monitor_name, new_state);
-        let description = format!("State changed from {:?} to {:?}", old_state, new_state);
+        let format = self.spec.format.as_ref();
+        let title = template::render(
+            format
+                .and_then(|f| f.title.as_deref())
+                .or(self.spec.message_format.as_deref())
+                .unwrap_or(DEFAULT_TITLE),
+            notification,
+        )?;
+        let description = template::render(
+            format
+                .and_then(|f| f.description.as_deref())
+                .unwrap_or(DEFAULT_DESCRIPTION),
+            notification,
+        )?;
 
-        let payload = serde_json::json!({
-            "embeds": [{
-                "title": title,
-                "description": description,
-                "color": color,
-                "timestamp": chrono::Utc::now().to_rfc3339()
-            }]
+        let mut embed = serde_json::json!({
+            "title": embed_text(title, TITLE_LIMIT),
+            "description": embed_text(description, DESCRIPTION_LIMIT),
+            "color": color,
+            "timestamp": notification.timestamp
         });
 
+        let mut payload = serde_json::json!({});
+
+        if let Some(format) = format {
+            if let Some(fields) = &format.fields {
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        Ok(serde_json::json!({
+                            "name": embed_text(
+                                template::render(&field.name, notification)?,
+                                FIELD_NAME_LIMIT
+                            ),
+                            "value": embed_text(
+                                template::render(&field.value, notification)?,
+                                FIELD_VALUE_LIMIT
+                            ),
+                            "inline": field.inline.unwrap_or(false)
+                        }))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                embed["fields"] = serde_json::json!(fields);
+            }
+            if let Some(username) = &format.username {
+                payload["username"] = serde_json::json!(template::render(username, notification)?);
+            }
+            if let Some(avatar_url) = &format.avatar_url {
+                payload["avatar_url"] =
+                    serde_json::json!(template::render(avatar_url, notification)?);
+            }
+            if let Some(mentions) = &format.mentions {
+                let state = format!("{:?}", notification.new_state);
+                if let Some(content) = mentions.get(&state) {
+                    payload["content"] =
+                        serde_json::json!(template::render(content, notification)?);
+                }
+            }
+        }
+
+        payload["embeds"] = serde_json::json!([embed]);
+        Ok(payload)
+    }
+
+    async fn send_discord_notification(
+        &self,
+        webhook_url: &str,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        // Build Discord payload
+        let payload = self.build_payload(notification)?;
+
         let http_client = reqwest::Client::new();
         let res = http_client.post(webhook_url).json(&payload).send().await?;
 
@@ -100,11 +229,22 @@ mod tests {
     use wiremock::matchers::{body_partial_json, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    #[tokio::test]
-    async fn test_send_discord_notification() {
-        let mock_server = MockServer::start().await;
+    fn notification(old_state: MonitorState, new_state: MonitorState) -> Notification {
+        Notification {
+            monitor_name: "test-monitor".to_string(),
+            monitor_namespace: "default".to_string(),
+            monitor_kind: "TCPMonitor".to_string(),
+            target: "localhost:80".to_string(),
+            old_state,
+            new_state,
+            message: Some("connection refused".to_string()),
+            response_time_ms: None,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
 
-        let notifier = DiscordNotifier::new(
+    fn notifier(format: Option<DiscordMessageFormat>) -> DiscordNotifier {
+        DiscordNotifier::new(
             "test-notifier",
             DiscordNotifierSpec {
                 webhook_secret_ref: SecretKeySelector {
@@ -112,8 +252,16 @@ mod tests {
                     key: "url".to_string(),
                 },
                 message_format: None,
+                format,
             },
-        );
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_discord_notification() {
+        let mock_server = MockServer::start().await;
+
+        let notifier = notifier(None);
 
         Mock::given(method("POST"))
             .and(path("/"))
@@ -131,12 +279,137 @@ mod tests {
         let result = notifier
             .send_discord_notification(
                 &mock_server.uri(),
-                "test-monitor",
-                &MonitorState::Healthy,
-                &MonitorState::Critical,
+                &notification(MonitorState::Healthy, MonitorState::Critical),
             )
             .await;
 
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_build_payload_with_message_format() {
+        let notifier = notifier(Some(DiscordMessageFormat {
+            title: Some("{{monitor_namespace}}/{{monitor_name}} {{new_state}}".to_string()),
+            description: Some("{{message}}".to_string()),
+            username: Some("KastleWatch".to_string()),
+            avatar_url: None,
+            fields: Some(vec![DiscordEmbedField {
+                name: "Target".to_string(),
+                value: "{{target}}".to_string(),
+                inline: Some(true),
+            }]),
+            mentions: Some(BTreeMap::from([(
+                "Critical".to_string(),
+                "<@&1234>".to_string(),
+            )])),
+        }));
+
+        let payload = notifier
+            .build_payload(&notification(MonitorState::Healthy, MonitorState::Critical))
+            .unwrap();
+
+        assert_eq!(payload["content"], "<@&1234>");
+        assert_eq!(payload["username"], "KastleWatch");
+        assert_eq!(
+            payload["embeds"][0]["title"],
+            "default/test-monitor Critical"
+        );
+        assert_eq!(payload["embeds"][0]["description"], "connection refused");
+        assert_eq!(payload["embeds"][0]["fields"][0]["value"], "localhost:80");
+
+        // No mention configured for Healthy
+        let payload = notifier
+            .build_payload(&notification(MonitorState::Critical, MonitorState::Healthy))
+            .unwrap();
+        assert!(payload.get("content").is_none());
+    }
+
+    #[test]
+    fn test_build_payload_fits_discord_limits() {
+        let notifier = notifier(Some(DiscordMessageFormat {
+            title: Some("{{message}}".to_string()),
+            description: Some("{{message}}".to_string()),
+            username: None,
+            avatar_url: None,
+            fields: Some(vec![DiscordEmbedField {
+                name: "Message".to_string(),
+                value: "{{message}}".to_string(),
+                inline: None,
+            }]),
+            mentions: None,
+        }));
+
+        let mut without_message = notification(MonitorState::Healthy, MonitorState::Critical);
+        without_message.message = None;
+        let payload = notifier.build_payload(&without_message).unwrap();
+        assert_eq!(payload["embeds"][0]["title"], "-");
+        assert_eq!(payload["embeds"][0]["description"], "-");
+        assert_eq!(payload["embeds"][0]["fields"][0]["value"], "-");
+
+        let mut long_message = notification(MonitorState::Healthy, MonitorState::Critical);
+        long_message.message = Some("é".repeat(5000));
+        let payload = notifier.build_payload(&long_message).unwrap();
+        let length = |value: &serde_json::Value| value.as_str().unwrap().chars().count();
+        assert_eq!(length(&payload["embeds"][0]["title"]), 256);
+        assert_eq!(length(&payload["embeds"][0]["description"]), 4096);
+        assert_eq!(length(&payload["embeds"][0]["fields"][0]["value"]), 1024);
+        assert!(
+            payload["embeds"][0]["description"]
+                .as_str()
+                .unwrap()
+                .ends_with('…')
+        );
+    }
+
+    #[test]
+    fn test_validate_message_format() {
+        let format = |title: &str, mention_state: &str| DiscordMessageFormat {
+            title: Some(title.to_string()),
+            description: None,
+            username: None,
+            avatar_url: None,
+            fields: None,
+            mentions: Some(BTreeMap::from([(
+                mention_state.to_string(),
+                "@here".to_string(),
+            )])),
+        };
+
+        assert!(
+            notifier(Some(format("{{monitor_name}}", "Critical")))
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            notifier(Some(format("{{monitor}}", "Critical")))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            notifier(Some(format("{{monitor_name}}", "Down")))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_deprecated_message_format_is_the_title() {
+        // Objects written before format existed keep deserializing and rendering
+        let notifier: DiscordNotifier = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kastlewatch.io/v1alpha1",
+            "kind": "DiscordNotifier",
+            "metadata": { "name": "old", "namespace": "default" },
+            "spec": {
+                "webhook_secret_ref": { "name": "discord", "key": "url" },
+                "message_format": "{{monitor_name}} went {{new_state}}"
+            }
+        }))
+        .unwrap();
+        assert!(notifier.validate().is_ok());
+
+        let payload = notifier
+            .build_payload(&notification(MonitorState::Healthy, MonitorState::Critical))
+            .unwrap();
+        assert_eq!(payload["embeds"][0]["title"], "test-monitor went Critical");
+    }
 }
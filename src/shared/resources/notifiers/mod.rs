@@ -1,22 +1,74 @@
+use crate::shared::metrics::METRICS;
 use crate::shared::resources::common::{ControllerResource, MonitorState, SecretKeySelector};
+use futures::FutureExt;
+use futures::future::BoxFuture;
 use k8s_openapi::api::core::v1::Secret;
 use kube::{Api, Client, ResourceExt};
+use serde::Serialize;
 use std::collections::BTreeMap;
+use std::sync::LazyLock;
 use tracing::{error, info};
 
 pub mod discord_notifier;
+pub mod slack_notifier;
+pub mod template;
+pub mod webhook_notifier;
+
+/// A monitor state change to be sent to notifiers
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub monitor_name: String,
+    pub monitor_namespace: String,
+    pub monitor_kind: String,
+    /// What the monitor checks (e.g. a URL or host:port)
+    pub target: String,
+    pub old_state: MonitorState,
+    pub new_state: MonitorState,
+    /// A human-readable explanation of the new state
+    pub message: Option<String>,
+    /// How long the probe took in milliseconds
+    pub response_time_ms: Option<u64>,
+    /// The time of the state change in RFC3339 format
+    pub timestamp: String,
+}
+
+impl Notification {
+    /// A representative notification, used to check that templates render
+    pub fn sample() -> Self {
+        Notification {
+            monitor_name: "monitor".to_string(),
+            monitor_namespace: "default".to_string(),
+            monitor_kind: "TCPMonitor".to_string(),
+            target: "localhost:80".to_string(),
+            old_state: MonitorState::Healthy,
+            new_state: MonitorState::Critical,
+            message: Some("message".to_string()),
+            response_time_ms: Some(0),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// The sample plus one with every optional field unset, as for errors and timeouts.
+    /// A template must render for both.
+    pub fn samples() -> [Self; 2] {
+        let sample = Self::sample();
+        let empty = Notification {
+            message: None,
+            response_time_ms: None,
+            ..sample.clone()
+        };
+        [sample, empty]
+    }
+}
 
 /// Trait for notifier resources
-#[allow(async_fn_in_trait)]
 pub trait NotifierResource: ControllerResource {
     /// Sends a notification
-    async fn notify(
+    fn notify(
         &self,
         client: Client,
-        monitor_name: &str,
-        old_state: &MonitorState,
-        new_state: &MonitorState,
-    ) -> anyhow::Result<()>;
+        notification: &Notification,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
 }
 
 /// Helper to get a secret value
@@ -43,26 +95,124 @@ pub async fn get_secret_value(
     ))
 }
 
+/// Lists the notifiers of one kind matching the label selector and notifies each of them
+async fn notify_matching<N>(
+    client: Client,
+    namespace: &str,
+    lp: &kube::api::ListParams,
+    notification: &Notification,
+) where
+    N: NotifierResource + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let api: Api<N> = Api::namespaced(client.clone(), namespace);
+
+    match api.list(lp).await {
+        Ok(notifiers) => {
+            for notifier in notifiers {
+                let notifier_name = notifier.name_any();
+                info!(
+                    "Sending notification to {} {} for {}",
+                    N::kind(&()),
+                    notifier_name,
+                    notification.monitor_name
+                );
+                let result = match notifier.notify(client.clone(), notification).await {
+                    Ok(()) => "success",
+                    Err(e) => {
+                        error!("Failed to notify {}: {:?}", notifier_name, e);
+                        "failure"
+                    }
+                };
+                METRICS.notifications.inc(vec![
+                    ("notifier_kind", N::kind(&()).to_string()),
+                    ("result", result.to_string()),
+                ]);
+            }
+        }
+        Err(e) => error!("Failed to list {}s: {:?}", N::kind(&()), e),
+    }
+}
+
+type NotifyFn =
+    for<'a> fn(Client, &'a str, &'a kube::api::ListParams, &'a Notification) -> BoxFuture<'a, ()>;
+
+fn notify_kind<'a, N>(
+    client: Client,
+    namespace: &'a str,
+    lp: &'a kube::api::ListParams,
+    notification: &'a Notification,
+) -> BoxFuture<'a, ()>
+where
+    N: NotifierResource + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    notify_matching::<N>(client, namespace, lp, notification).boxed()
+}
+
+/// The notifier kinds that process_notifications dispatches to
+pub struct NotifierRegistry {
+    kinds: Vec<(String, NotifyFn)>,
+}
+
+impl NotifierRegistry {
+    pub fn new() -> Self {
+        NotifierRegistry { kinds: Vec::new() }
+    }
+
+    /// Adds a notifier kind to the registry
+    pub fn register<N>(mut self) -> Self
+    where
+        N: NotifierResource + serde::de::DeserializeOwned + std::fmt::Debug,
+    {
+        self.kinds
+            .push((N::kind(&()).to_string(), notify_kind::<N>));
+        self
+    }
+
+    /// Returns the names of the registered kinds
+    pub fn kinds(&self) -> Vec<&str> {
+        self.kinds.iter().map(|(kind, _)| kind.as_str()).collect()
+    }
+
+    /// Notifies the notifiers of every registered kind matching the label selector
+    pub async fn notify_all(
+        &self,
+        client: Client,
+        namespace: &str,
+        lp: &kube::api::ListParams,
+        notification: &Notification,
+    ) {
+        futures::future::join_all(
+            self.kinds
+                .iter()
+                .map(|(_, notify)| notify(client.clone(), namespace, lp, notification)),
+        )
+        .await;
+    }
+}
+
+impl Default for NotifierRegistry {
+    /// A registry with all notifier kinds shipped with KastleWatch
+    fn default() -> Self {
+        NotifierRegistry::new()
+            .register::<discord_notifier::v1alpha1::DiscordNotifier>()
+            .register::<slack_notifier::v1alpha1::SlackNotifier>()
+            .register::<webhook_notifier::v1alpha1::WebhookNotifier>()
+    }
+}
+
+static REGISTRY: LazyLock<NotifierRegistry> = LazyLock::new(NotifierRegistry::default);
+
 /// Process notifications for a monitor state change
 pub async fn process_notifications(
     client: Client,
-    monitor_name: &str,
-    monitor_namespace: &str,
     match_labels: &Option<BTreeMap<String, String>>,
-    old_state: &MonitorState,
-    new_state: &MonitorState,
+    notification: &Notification,
 ) {
-    if old_state == new_state {
+    if notification.old_state == notification.new_state {
         return;
     }
 
     if let Some(labels) = match_labels {
-        // TODO: This currently only supports DiscordNotifier.
-        // In the future we might want a way to discover ALL types of notifiers.
-        // For now, we will just query for DiscordNotifiers.
-        use discord_notifier::v1alpha1::DiscordNotifier;
-
-        let api: Api<DiscordNotifier> = Api::namespaced(client.clone(), monitor_namespace);
         let lp = kube::api::ListParams::default().labels(
             &labels
                 .iter()
@@ -71,23 +221,21 @@ pub async fn process_notifications(
                 .join(","),
         );
 
-        match api.list(&lp).await {
-            Ok(notifiers) => {
-                for notifier in notifiers {
-                    let notifier_name = notifier.name_any();
-                    info!(
-                        "Sending notification to {} for {}",
-                        notifier_name, monitor_name
-                    );
-                    if let Err(e) = notifier
-                        .notify(client.clone(), monitor_name, old_state, new_state)
-                        .await
-                    {
-                        error!("Failed to notify {}: {:?}", notifier_name, e);
-                    }
-                }
-            }
-            Err(e) => error!("Failed to list notifiers: {:?}", e),
-        }
+        REGISTRY
+            .notify_all(client, &notification.monitor_namespace, &lp, notification)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_registry_has_all_kinds() {
+        assert_eq!(
+            NotifierRegistry::default().kinds(),
+            vec!["DiscordNotifier", "SlackNotifier", "WebhookNotifier"]
+        );
     }
 }
@@ -0,0 +1,185 @@
+use crate::shared::context::Context;
+use crate::shared::resources::common::{ControllerResource, MonitorState, SecretKeySelector};
+use crate::shared::resources::notifiers::{self, Notification, NotifierResource};
+use kube::{Client, CustomResource, ResourceExt, runtime::controller::Action};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::error;
+
+/// Specification for the SlackNotifier resource
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "kastlewatch.io",
+    version = "v1alpha1",
+    kind = "SlackNotifier",
+    namespaced
+)]
+pub struct SlackNotifierSpec {
+    /// Reference to the secret containing the incoming webhook URL
+    pub webhook_secret_ref: SecretKeySelector,
+}
+
+impl ControllerResource for SlackNotifier {
+    fn success_policy(&self) -> Action {
+        Action::requeue(Duration::from_secs(3600))
+    }
+
+    fn error_policy(&self, error: &anyhow::Error, _ctx: Arc<Context>) -> Action {
+        let name = self.name_any();
+        error!("Reconciliation error for \"{}\": {:?}", name, error);
+        Action::requeue(Duration::from_secs(60))
+    }
+}
+
+impl NotifierResource for SlackNotifier {
+    async fn notify(&self, client: Client, notification: &Notification) -> anyhow::Result<()> {
+        // Get webhook URL
+        let ns = self.namespace().unwrap_or_else(|| "default".to_string());
+        let webhook_url =
+            notifiers::get_secret_value(client, &ns, &self.spec.webhook_secret_ref).await?;
+
+        self.send_slack_notification(&webhook_url, notification)
+            .await
+    }
+}
+
+impl SlackNotifier {
+    /// Builds the Block Kit payload, wrapped in an attachment so Slack shows the state colour
+    fn build_payload(notification: &Notification) -> serde_json::Value {
+        let color = match notification.new_state {
+            MonitorState::Healthy => "#00FF00",
+            MonitorState::Warning => "#FFFF00",
+            MonitorState::Critical => "#FF0000",
+            MonitorState::NoData => "#808080",
+            MonitorState::Error => "#800080",
+        };
+
+        let title = format!(
+            "Monitor {} is {:?}",
+            notification.monitor_name, notification.new_state
+        );
+        let mut description = format!(
+            "State changed from {:?} to {:?}",
+            notification.old_state, notification.new_state
+        );
+        if let Some(message) = &notification.message {
+            description.push_str(&format!("\n>{}", message));
+        }
+
+        let target = &notification.target;
+        let target = if target.starts_with("http://") || target.starts_with("https://") {
+            format!("<{}|{}>", target, target)
+        } else {
+            format!("`{}`", target)
+        };
+
+        serde_json::json!({
+            "text": title,
+            "attachments": [{
+                "color": color,
+                "blocks": [
+                    {
+                        "type": "header",
+                        "text": { "type": "plain_text", "text": title }
+                    },
+                    {
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": description }
+                    },
+                    {
+                        "type": "section",
+                        "fields": [
+                            { "type": "mrkdwn", "text": format!("*Kind*\n{}", notification.monitor_kind) },
+                            { "type": "mrkdwn", "text": format!("*Namespace*\n{}", notification.monitor_namespace) },
+                            { "type": "mrkdwn", "text": format!("*Target*\n{}", target) }
+                        ]
+                    },
+                    {
+                        "type": "context",
+                        "elements": [
+                            { "type": "mrkdwn", "text": notification.timestamp }
+                        ]
+                    }
+                ]
+            }]
+        })
+    }
+
+    async fn send_slack_notification(
+        &self,
+        webhook_url: &str,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        let payload = Self::build_payload(notification);
+
+        let http_client = reqwest::Client::new();
+        let res = http_client.post(webhook_url).json(&payload).send().await?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!("Slack API returned {}", res.status()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_send_slack_notification() {
+        let mock_server = MockServer::start().await;
+
+        let notifier = SlackNotifier::new(
+            "test-notifier",
+            SlackNotifierSpec {
+                webhook_secret_ref: SecretKeySelector {
+                    name: "test-secret".to_string(),
+                    key: "url".to_string(),
+                },
+            },
+        );
+
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(body_partial_json(serde_json::json!({
+                "text": "Monitor test-monitor is Critical",
+                "attachments": [{
+                    "color": "#FF0000"
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let notification = Notification {
+            monitor_name: "test-monitor".to_string(),
+            monitor_namespace: "default".to_string(),
+            monitor_kind: "HTTPMonitor".to_string(),
+            target: "https://example.com/healthz".to_string(),
+            old_state: MonitorState::Healthy,
+            new_state: MonitorState::Critical,
+            message: Some("status 503 not in [200]".to_string()),
+            response_time_ms: Some(12),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+        };
+
+        let result = notifier
+            .send_slack_notification(&mock_server.uri(), &notification)
+            .await;
+
+        assert!(result.is_ok());
+
+        let payload = SlackNotifier::build_payload(&notification);
+        let fields = &payload["attachments"][0]["blocks"][2]["fields"];
+        assert_eq!(
+            fields[2]["text"],
+            "*Target*\n<https://example.com/healthz|https://example.com/healthz>"
+        );
+    }
+}
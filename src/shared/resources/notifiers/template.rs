@@ -0,0 +1,135 @@
+use crate::shared::resources::notifiers::Notification;
+
+/// The placeholders available to notification templates, written as `{{name}}`
+pub const PLACEHOLDERS: &[&str] = &[
+    "monitor_name",
+    "monitor_namespace",
+    "monitor_kind",
+    "target",
+    "old_state",
+    "new_state",
+    "message",
+    "response_time_ms",
+    "timestamp",
+];
+
+fn placeholder_value(notification: &Notification, placeholder: &str) -> String {
+    match placeholder {
+        "monitor_name" => notification.monitor_name.clone(),
+        "monitor_namespace" => notification.monitor_namespace.clone(),
+        "monitor_kind" => notification.monitor_kind.clone(),
+        "target" => notification.target.clone(),
+        "old_state" => format!("{:?}", notification.old_state),
+        "new_state" => format!("{:?}", notification.new_state),
+        "message" => notification.message.clone().unwrap_or_default(),
+        "response_time_ms" => notification
+            .response_time_ms
+            .map(|ms| ms.to_string())
+            .unwrap_or_default(),
+        "timestamp" => notification.timestamp.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Splits a template into literal text and placeholder names
+fn parse(template: &str) -> anyhow::Result<Vec<(bool, &str)>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        parts.push((false, &rest[..start]));
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in template: {}", template))?;
+        let name = after[..end].trim();
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(anyhow::anyhow!(
+                "Unknown placeholder \"{}\" in template, expected one of {:?}",
+                name,
+                PLACEHOLDERS
+            ));
+        }
+        parts.push((true, name));
+        rest = &after[end + 2..];
+    }
+    parts.push((false, rest));
+    Ok(parts)
+}
+
+/// Checks that a template only uses known placeholders
+pub fn validate(template: &str) -> anyhow::Result<()> {
+    parse(template).map(|_| ())
+}
+
+/// Renders a template, substituting placeholders with the notification values
+pub fn render(template: &str, notification: &Notification) -> anyhow::Result<String> {
+    render_with(template, notification, |value| value)
+}
+
+/// Renders a template whose placeholders sit inside JSON strings, escaping the substituted values
+pub fn render_json(template: &str, notification: &Notification) -> anyhow::Result<String> {
+    render_with(template, notification, |value| {
+        let quoted = serde_json::Value::String(value).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    })
+}
+
+fn render_with(
+    template: &str,
+    notification: &Notification,
+    escape: impl Fn(String) -> String,
+) -> anyhow::Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    for (is_placeholder, part) in parse(template)? {
+        if is_placeholder {
+            rendered.push_str(&escape(placeholder_value(notification, part)));
+        } else {
+            rendered.push_str(part);
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::resources::common::MonitorState;
+
+    fn notification() -> Notification {
+        Notification {
+            monitor_name: "api".to_string(),
+            monitor_namespace: "prod".to_string(),
+            monitor_kind: "HTTPMonitor".to_string(),
+            target: "https://api.example.com/healthz".to_string(),
+            old_state: MonitorState::Healthy,
+            new_state: MonitorState::Critical,
+            message: Some("status 503 \"unavailable\"".to_string()),
+            response_time_ms: Some(42),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = render(
+            "{{monitor_kind}} {{ monitor_namespace }}/{{monitor_name}}: {{old_state}} -> {{new_state}} ({{response_time_ms}}ms)",
+            &notification(),
+        )
+        .unwrap();
+        assert_eq!(rendered, "HTTPMonitor prod/api: Healthy -> Critical (42ms)");
+    }
+
+    #[test]
+    fn test_render_json_escapes_values() {
+        let rendered = render_json(r#"{"text": "{{message}}"}"#, &notification()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(body["text"], "status 503 \"unavailable\"");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("{{monitor_name}} is {{new_state}}").is_ok());
+        assert!(validate("{{monitor}}").is_err());
+        assert!(validate("{{monitor_name").is_err());
+    }
+}
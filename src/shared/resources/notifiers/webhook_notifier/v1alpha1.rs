@@ -0,0 +1,270 @@
+use crate::shared::context::Context;
+use crate::shared::resources::common::{ControllerResource, SecretKeySelector};
+use crate::shared::resources::notifiers::{self, Notification, NotifierResource, template};
+use kube::{Client, CustomResource, ResourceExt, runtime::controller::Action};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::error;
+
+const DEFAULT_CONTENT_TYPE: &str = "application/json";
+/// Notifications are sent one after another, so a hung webhook must not hold up the others
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum WebhookMethod {
+    POST,
+    PUT,
+    PATCH,
+}
+
+/// A header sent with the webhook request
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct WebhookHeader {
+    /// The header name
+    pub name: String,
+    /// The header value. Exactly one of value and value_secret_ref must be set.
+    pub value: Option<String>,
+    /// Reference to the secret containing the header value
+    pub value_secret_ref: Option<SecretKeySelector>,
+}
+
+/// Specification for the WebhookNotifier resource
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "kastlewatch.io",
+    version = "v1alpha1",
+    kind = "WebhookNotifier",
+    namespaced
+)]
+pub struct WebhookNotifierSpec {
+    /// Reference to the secret containing the URL to call
+    pub url_secret_ref: SecretKeySelector,
+    /// Support POST, PUT or PATCH. Optional. Defaults to POST.
+    pub method: Option<WebhookMethod>,
+    /// Headers to send with the request. Optional.
+    pub headers: Option<Vec<WebhookHeader>>,
+    /// Template for the request body. Optional. If not defined, send the notification as JSON.
+    /// Placeholders: {{monitor_name}}, {{monitor_namespace}}, {{monitor_kind}}, {{target}}, {{old_state}}, {{new_state}},
+    /// {{message}}, {{response_time_ms}} and {{timestamp}}. Values are JSON-escaped when the content type is JSON.
+    /// message and response_time_ms can be empty, so quote them in JSON templates.
+    pub body_template: Option<String>,
+    /// The Content-Type of the body. Optional. Defaults to application/json.
+    pub content_type: Option<String>,
+}
+
+impl ControllerResource for WebhookNotifier {
+    fn success_policy(&self) -> Action {
+        Action::requeue(Duration::from_secs(3600))
+    }
+
+    fn error_policy(&self, error: &anyhow::Error, _ctx: Arc<Context>) -> Action {
+        let name = self.name_any();
+        error!("Reconciliation error for \"{}\": {:?}", name, error);
+        Action::requeue(Duration::from_secs(60))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for header in self.spec.headers.iter().flatten() {
+            if header.value.is_some() == header.value_secret_ref.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Header {} must set exactly one of value and value_secret_ref",
+                    header.name
+                ));
+            }
+        }
+
+        if let Some(body_template) = &self.spec.body_template {
+            template::validate(body_template)?;
+            if self.is_json() {
+                for sample in Notification::samples() {
+                    let rendered = template::render_json(body_template, &sample)?;
+                    serde_json::from_str::<serde_json::Value>(&rendered).map_err(|e| {
+                        anyhow::anyhow!("Body template does not render to valid JSON: {}", e)
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NotifierResource for WebhookNotifier {
+    async fn notify(&self, client: Client, notification: &Notification) -> anyhow::Result<()> {
+        let ns = self.namespace().unwrap_or_else(|| "default".to_string());
+        let url =
+            notifiers::get_secret_value(client.clone(), &ns, &self.spec.url_secret_ref).await?;
+        // Secrets written with echo or a text editor end with a newline
+        let url = url.trim();
+
+        let mut headers = Vec::new();
+        for header in self.spec.headers.iter().flatten() {
+            let value = match (&header.value, &header.value_secret_ref) {
+                (Some(value), _) => value.clone(),
+                (None, Some(secret_ref)) => {
+                    notifiers::get_secret_value(client.clone(), &ns, secret_ref)
+                        .await?
+                        .trim()
+                        .to_string()
+                }
+                (None, None) => continue,
+            };
+            headers.push((header.name.clone(), value));
+        }
+
+        self.send_webhook(url, &headers, notification).await
+    }
+}
+
+impl WebhookNotifier {
+    fn is_json(&self) -> bool {
+        self.content_type().contains("json")
+    }
+
+    fn content_type(&self) -> &str {
+        self.spec
+            .content_type
+            .as_deref()
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+    }
+
+    /// Builds the request body from the template, or the notification itself when no template is set
+    pub fn render_body(&self, notification: &Notification) -> anyhow::Result<String> {
+        match &self.spec.body_template {
+            Some(body_template) if self.is_json() => {
+                template::render_json(body_template, notification)
+            }
+            Some(body_template) => template::render(body_template, notification),
+            None => Ok(serde_json::to_string(notification)?),
+        }
+    }
+
+    async fn send_webhook(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        let body = self.render_body(notification)?;
+
+        let http_client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let mut req_builder = match self.spec.method.as_ref().unwrap_or(&WebhookMethod::POST) {
+            WebhookMethod::POST => http_client.post(url),
+            WebhookMethod::PUT => http_client.put(url),
+            WebhookMethod::PATCH => http_client.patch(url),
+        };
+        req_builder = req_builder.header(reqwest::header::CONTENT_TYPE, self.content_type());
+        for (name, value) in headers {
+            req_builder = req_builder.header(name.as_str(), value.as_str());
+        }
+
+        let res = req_builder.body(body).send().await?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!("Webhook returned {}", res.status()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::resources::common::MonitorState;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn notifier(body_template: Option<&str>) -> WebhookNotifier {
+        WebhookNotifier::new(
+            "test-notifier",
+            WebhookNotifierSpec {
+                url_secret_ref: SecretKeySelector {
+                    name: "test-secret".to_string(),
+                    key: "url".to_string(),
+                },
+                method: Some(WebhookMethod::PUT),
+                headers: None,
+                body_template: body_template.map(str::to_string),
+                content_type: None,
+            },
+        )
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            monitor_name: "test-monitor".to_string(),
+            monitor_namespace: "default".to_string(),
+            monitor_kind: "TCPMonitor".to_string(),
+            target: "localhost:80".to_string(),
+            old_state: MonitorState::Healthy,
+            new_state: MonitorState::Critical,
+            message: Some("connection refused".to_string()),
+            response_time_ms: None,
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_webhook() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("PUT"))
+            .and(path("/hook"))
+            .and(header("X-Token", "abc"))
+            .and(body_json(serde_json::json!({
+                "summary": "default/test-monitor is Critical",
+                "detail": "connection refused"
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let notifier = notifier(Some(
+            r#"{"summary": "{{monitor_namespace}}/{{monitor_name}} is {{new_state}}", "detail": "{{message}}"}"#,
+        ));
+
+        let result = notifier
+            .send_webhook(
+                &format!("{}/hook", mock_server.uri()),
+                &[("X-Token".to_string(), "abc".to_string())],
+                &notification(),
+            )
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_json_template() {
+        assert!(
+            notifier(Some(r#"{"text": "{{monitor_name}}""#))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            notifier(Some(r#"{"text": "{{unknown}}"}"#))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            notifier(Some(r#"{"text": "{{monitor_name}}"}"#))
+                .validate()
+                .is_ok()
+        );
+        // Renders "latency": , when a check has no response time
+        assert!(
+            notifier(Some(r#"{"latency": {{response_time_ms}}}"#))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            notifier(Some(r#"{"latency": "{{response_time_ms}}"}"#))
+                .validate()
+                .is_ok()
+        );
+    }
+}
@@ -1,7 +1,405 @@
-use crate::shared::resources::common::{MonitorResource, MonitorState};
+use crate::shared::context::AppState;
+use crate::shared::metrics::{METRICS, monitor_labels};
+use crate::shared::queue::Rejection;
+use crate::shared::resources::common::{
+    CheckResult, LocationStatus, MonitorCondition, MonitorConfigSpec, MonitorResource,
+    MonitorState, MonitorStatus, WORKER_UNREACHABLE_CONDITION, set_condition,
+};
 use crate::shared::resources::notifiers;
+use axum::{
+    extract::{Json, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
 use kube::{Api, Client, ResourceExt};
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+const DEFAULT_RETRY_DELAY: u32 = 1;
+const DEFAULT_RETRY_BACKOFF: u32 = 2;
+const DEFAULT_FAILURE_THRESHOLD: u32 = 1;
+const DEFAULT_SUCCESS_THRESHOLD: u32 = 1;
+/// Seconds the controller is asked to wait before resending a rejected check
+const RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Runs the check, retrying failures up to `retries` times with an increasing delay.
+/// Retries stop early when the next attempt could run past the polling frequency, so checks of one monitor never overlap.
+/// A check that could not be performed is a broken monitor and is not retried.
+/// Returns the last result together with the number of attempts made.
+pub async fn check_with_retries<T>(
+    monitor: &T,
+    client: Option<Client>,
+) -> (anyhow::Result<CheckResult>, u32)
+where
+    T: MonitorResource,
+{
+    let config = monitor.monitor_config();
+    let max_attempts = config.retries.saturating_add(1);
+    let backoff = config.retry_backoff.unwrap_or(DEFAULT_RETRY_BACKOFF) as u64;
+    let mut delay = config.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY) as u64;
+    let budget = Duration::from_secs(config.polling_frequency as u64);
+    let timeout = Duration::from_secs(config.timeout as u64);
+    let started = Instant::now();
+
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = monitor.check(client.clone()).await;
+
+        let failed = !matches!(
+            result,
+            Ok(CheckResult {
+                state: MonitorState::Healthy | MonitorState::Warning,
+                ..
+            })
+        );
+        if result.is_err() || !failed || attempts >= max_attempts {
+            return (result, attempts);
+        }
+        let next_attempt_done = started.elapsed() + Duration::from_secs(delay) + timeout;
+        if !budget.is_zero() && next_attempt_done > budget {
+            info!(
+                "Check attempt {}/{} failed for {}, no time left to retry before the next check",
+                attempts,
+                max_attempts,
+                monitor.name_any()
+            );
+            return (result, attempts);
+        }
+
+        info!(
+            "Check attempt {}/{} failed for {}, retrying in {}s",
+            attempts,
+            max_attempts,
+            monitor.name_any(),
+            delay
+        );
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+        delay = delay.saturating_mul(backoff);
+    }
+}
+
+/// The result of applying a check to the previous status
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub state: MonitorState,
+    pub consecutive_failures: u32,
+    pub consecutive_successes: u32,
+    pub recent_transitions: Vec<String>,
+    pub flapping: bool,
+}
+
+/// Applies the failure/success thresholds and flap detection to a check result.
+/// A monitor without data (or in Error) adopts the next result immediately, and check errors bypass the thresholds.
+pub fn evaluate_transition(
+    config: &MonitorConfigSpec,
+    previous: Option<&MonitorStatus>,
+    result: &MonitorState,
+    now: DateTime<Utc>,
+) -> Transition {
+    let old_state = previous.map(|s| s.state.clone()).unwrap_or_default();
+    let mut failures = previous.and_then(|s| s.consecutive_failures).unwrap_or(0);
+    let mut successes = previous.and_then(|s| s.consecutive_successes).unwrap_or(0);
+    let failure_threshold = config
+        .failure_threshold
+        .unwrap_or(DEFAULT_FAILURE_THRESHOLD);
+    let success_threshold = config
+        .success_threshold
+        .unwrap_or(DEFAULT_SUCCESS_THRESHOLD);
+
+    let state = match result {
+        MonitorState::Critical => {
+            failures = failures.saturating_add(1);
+            successes = 0;
+            if matches!(old_state, MonitorState::NoData | MonitorState::Error)
+                || failures >= failure_threshold
+            {
+                MonitorState::Critical
+            } else {
+                old_state.clone()
+            }
+        }
+        MonitorState::Healthy | MonitorState::Warning => {
+            successes = successes.saturating_add(1);
+            failures = 0;
+            if old_state != MonitorState::Critical || successes >= success_threshold {
+                result.clone()
+            } else {
+                MonitorState::Critical
+            }
+        }
+        MonitorState::NoData | MonitorState::Error => result.clone(),
+    };
+
+    let mut recent_transitions = previous
+        .and_then(|s| s.recent_transitions.clone())
+        .unwrap_or_default();
+    if state != old_state && old_state != MonitorState::NoData {
+        recent_transitions.push(now.to_rfc3339());
+    }
+
+    let flapping = match &config.flap_detection {
+        Some(flap) => {
+            let cutoff = now - chrono::Duration::seconds(flap.window as i64);
+            recent_transitions.retain(|t| {
+                DateTime::parse_from_rfc3339(t)
+                    .map(|t| t.with_timezone(&Utc) > cutoff)
+                    .unwrap_or(false)
+            });
+            recent_transitions.len() as u32 >= flap.max_transitions
+        }
+        None => {
+            recent_transitions.clear();
+            false
+        }
+    };
+
+    Transition {
+        state,
+        consecutive_failures: failures,
+        consecutive_successes: successes,
+        recent_transitions,
+        flapping,
+    }
+}
+
+/// Builds the Ready and Degraded conditions, keeping the transition time of conditions that did not change.
+/// Other conditions are carried over, with WorkerUnreachable cleared since the worker received the check.
+pub fn build_conditions(
+    previous: Option<&[MonitorCondition]>,
+    state: &MonitorState,
+    flapping: bool,
+    message: Option<&str>,
+    now: DateTime<Utc>,
+) -> Vec<MonitorCondition> {
+    let ready = match state {
+        MonitorState::Healthy | MonitorState::Warning => "True",
+        MonitorState::Critical => "False",
+        MonitorState::NoData | MonitorState::Error => "Unknown",
+    };
+    let (degraded, degraded_reason) = if flapping {
+        ("True", "Flapping")
+    } else if *state == MonitorState::Warning {
+        ("True", "Warning")
+    } else {
+        ("False", "NotDegraded")
+    };
+
+    let mut conditions = previous.map(<[_]>::to_vec).unwrap_or_default();
+    let ready_reason = format!("{:?}", state);
+    set_condition(&mut conditions, "Ready", ready, &ready_reason, message, now);
+    set_condition(
+        &mut conditions,
+        "Degraded",
+        degraded,
+        degraded_reason,
+        message,
+        now,
+    );
+    if conditions
+        .iter()
+        .any(|c| c.type_ == WORKER_UNREACHABLE_CONDITION)
+    {
+        set_condition(
+            &mut conditions,
+            WORKER_UNREACHABLE_CONDITION,
+            "False",
+            "WorkerReachable",
+            None,
+            now,
+        );
+    }
+    conditions
+}
+
+async fn emit_event<T>(
+    client: Client,
+    name: &str,
+    ns: &str,
+    reason: &str,
+    message: &str,
+    type_: &str,
+) where
+    T: MonitorResource,
+{
+    let kind = T::kind(&());
+    let api_version = T::api_version(&());
+
+    if let Err(e) = crate::shared::resources::common::publish_event(
+        client,
+        name,
+        &kind,
+        &api_version,
+        ns,
+        reason,
+        message,
+        type_,
+    )
+    .await
+    {
+        error!("Failed to publish event for {}: {:?}", name, e);
+    }
+}
+
+/// Queues the check on the worker, or rejects it when the worker or the target host is saturated
+pub fn enqueue<T>(state: AppState, monitor: T) -> Response
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let Some(client) = state.client.clone() else {
+        return (
+            StatusCode::NOT_IMPLEMENTED,
+            "worker runs in probe-only mode, use the probe endpoint",
+        )
+            .into_response();
+    };
+    match state.queue.try_admit(&monitor.target_host()) {
+        Ok(ticket) => {
+            tokio::spawn(ticket.run(generic_worker_handler(monitor, client)));
+            StatusCode::OK.into_response()
+        }
+        Err(rejection) => rejected(&state, &monitor, rejection),
+    }
+}
+
+/// Builds the response for a rejected check, with a Retry-After header so the caller can back off
+pub(crate) fn rejected<T>(state: &AppState, monitor: &T, rejection: Rejection) -> Response
+where
+    T: MonitorResource,
+{
+    let status = match rejection {
+        Rejection::QueueFull => StatusCode::SERVICE_UNAVAILABLE,
+        Rejection::HostBusy => StatusCode::TOO_MANY_REQUESTS,
+    };
+    warn!(
+        "Rejecting {} {} ({}), {} checks pending",
+        T::kind(&()),
+        monitor.name_any(),
+        status,
+        state.queue.pending()
+    );
+    (
+        status,
+        [(header::RETRY_AFTER, RETRY_AFTER_SECONDS.to_string())],
+    )
+        .into_response()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CheckParams {
+    /// Must be true, checks run through this endpoint never touch status or notifiers
+    #[serde(rename = "dryRun", default)]
+    pub dry_run: bool,
+}
+
+/// The result of a check run through the check endpoint
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResponse {
+    pub kind: String,
+    pub name: String,
+    pub target: String,
+    pub result: CheckResult,
+}
+
+/// Validates the monitor and runs its check inline, returning the result to the caller
+/// without patching status or sending notifications
+pub async fn check_inline<T>(
+    State(state): State<AppState>,
+    Query(params): Query<CheckParams>,
+    Json(monitor): Json<T>,
+) -> Response
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    if !params.dry_run {
+        return (
+            StatusCode::BAD_REQUEST,
+            "only dryRun=true is supported, the controller schedules real checks",
+        )
+            .into_response();
+    }
+    if let Err(e) = monitor.validate() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response();
+    }
+
+    let ticket = match state.queue.try_admit(&monitor.target_host()) {
+        Ok(ticket) => ticket,
+        Err(rejection) => return rejected(&state, &monitor, rejection),
+    };
+
+    info!("Dry run of {} {}", T::kind(&()), monitor.name_any());
+    let result = match ticket.run(monitor.check(state.client.clone())).await {
+        Ok(result) => result,
+        Err(e) => CheckResult::new(MonitorState::Error, e.to_string()),
+    };
+
+    Json(CheckResponse {
+        kind: T::kind(&()).to_string(),
+        name: monitor.name_any(),
+        target: monitor.target(),
+        result,
+    })
+    .into_response()
+}
+
+/// How long the metric series of a monitor outlive its last check, in polling periods
+const METRICS_TTL_POLLING_PERIODS: u32 = 3;
+
+/// Drops the series of monitors that were deleted or moved to another worker
+fn metrics_ttl(config: &MonitorConfigSpec) -> Duration {
+    Duration::from_secs(config.polling_frequency.max(1) as u64) * METRICS_TTL_POLLING_PERIODS
+}
+
+/// The outcome of probing a monitor's target, retries included
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ProbeResult {
+    pub result: CheckResult,
+    pub attempts: u32,
+}
+
+/// Runs the check with retries, turning a failure to perform it into an Error result
+pub async fn probe<T>(monitor: &T, client: Option<Client>) -> ProbeResult
+where
+    T: MonitorResource,
+{
+    let started = Instant::now();
+    let (check_result, attempts) = check_with_retries(monitor, client).await;
+    let labels = monitor_labels(
+        &T::kind(&()),
+        &monitor.namespace().unwrap_or_default(),
+        &monitor.name_any(),
+    );
+    METRICS
+        .probe_duration
+        .observe(labels.clone(), started.elapsed());
+    METRICS.touch_monitor(&labels, metrics_ttl(monitor.monitor_config()));
+    let result = match check_result {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Check failed for {}: {:?}", monitor.name_any(), e);
+            CheckResult::new(MonitorState::Error, e.to_string())
+        }
+    };
+    ProbeResult { result, attempts }
+}
+
+/// Probes the target and returns the result to the controller, which records it.
+/// Used by workers without Kubernetes credentials.
+pub async fn probe_inline<T>(State(state): State<AppState>, Json(monitor): Json<T>) -> Response
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let ticket = match state.queue.try_admit(&monitor.target_host()) {
+        Ok(ticket) => ticket,
+        Err(rejection) => return rejected(&state, &monitor, rejection),
+    };
+
+    info!("Worker probing {}: {}", T::kind(&()), monitor.name_any());
+    Json(ticket.run(probe(&monitor, state.client.clone())).await).into_response()
+}
 
 pub async fn generic_worker_handler<T>(monitor: T, client: Client)
 where
@@ -9,30 +407,196 @@ where
 {
     info!("Worker received {}: {}", T::kind(&()), monitor.name_any());
 
+    let probe_result = probe(&monitor, Some(client.clone())).await;
+    record_result(&monitor, client, probe_result, None).await;
+}
+
+/// Combines the results from several locations into one.
+/// The monitor goes Critical (or Warning) only if at least `quorum` locations see it so, which
+/// keeps a network blip at a single location from raising an alarm. Locations that could not
+/// perform the check do not vote, and the result is Error when fewer than `quorum` locations vote.
+pub fn aggregate_locations(results: &[(String, ProbeResult)], quorum: Option<u32>) -> ProbeResult {
+    let attempts = results.iter().map(|(_, p)| p.attempts).max().unwrap_or(0);
+    let describe = |(name, probe): &&(String, ProbeResult)| {
+        format!(
+            "{}: {}",
+            name,
+            probe
+                .result
+                .message
+                .clone()
+                .unwrap_or_else(|| format!("{:?}", probe.result.state))
+        )
+    };
+
+    let voting: Vec<&(String, ProbeResult)> = results
+        .iter()
+        .filter(|(_, p)| {
+            matches!(
+                p.result.state,
+                MonitorState::Healthy | MonitorState::Warning | MonitorState::Critical
+            )
+        })
+        .collect();
+    if voting.is_empty() {
+        let message = results.iter().map(|r| describe(&r)).collect::<Vec<_>>();
+        return ProbeResult {
+            result: CheckResult::new(MonitorState::Error, message.join("; ")),
+            attempts,
+        };
+    }
+
+    // An explicit quorum is never lowered, a single location must not decide for several
+    let quorum = quorum
+        .map(|q| q as usize)
+        .unwrap_or(voting.len() / 2 + 1)
+        .max(1);
+    if voting.len() < quorum {
+        let silent: Vec<String> = results
+            .iter()
+            .filter(|r| !voting.contains(r))
+            .map(|r| describe(&r))
+            .collect();
+        return ProbeResult {
+            result: CheckResult::new(
+                MonitorState::Error,
+                format!(
+                    "Not enough locations: {} of {} needed for the quorum returned a result ({})",
+                    voting.len(),
+                    quorum,
+                    silent.join("; ")
+                ),
+            ),
+            attempts,
+        };
+    }
+    let critical = voting
+        .iter()
+        .filter(|(_, p)| p.result.state == MonitorState::Critical)
+        .count();
+    let degraded = voting
+        .iter()
+        .filter(|(_, p)| p.result.state != MonitorState::Healthy)
+        .count();
+    let (state, count) = if critical >= quorum {
+        (MonitorState::Critical, critical)
+    } else if degraded >= quorum {
+        (MonitorState::Warning, degraded)
+    } else {
+        (MonitorState::Healthy, voting.len() - degraded)
+    };
+
+    let failing: Vec<String> = voting
+        .iter()
+        .filter(|(_, p)| p.result.state != MonitorState::Healthy)
+        .map(describe)
+        .collect();
+    let mut message = format!("{:?} at {}/{} locations", state, count, voting.len());
+    if !failing.is_empty() {
+        message.push_str(&format!(" ({})", failing.join("; ")));
+    }
+
+    // The median is not thrown off by a single slow location
+    let mut response_times: Vec<u64> = voting
+        .iter()
+        .filter_map(|(_, p)| p.result.response_time_ms)
+        .collect();
+    response_times.sort_unstable();
+
+    ProbeResult {
+        result: CheckResult {
+            state,
+            message: Some(message),
+            response_time_ms: response_times.get(response_times.len() / 2).copied(),
+            status_code: None,
+        },
+        attempts,
+    }
+}
+
+/// Writes a probe result to the monitor status, then emits events and notifies on state changes
+pub async fn record_result<T>(
+    monitor: &T,
+    client: Client,
+    probe_result: ProbeResult,
+    locations: Option<Vec<LocationStatus>>,
+) where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
     let name = monitor.name_any();
     let ns = monitor.namespace().unwrap_or_else(|| "default".to_string());
     let api: Api<T> = Api::namespaced(client.clone(), &ns);
+    let config = monitor.monitor_config();
 
     let old_state = match monitor.status() {
         Some(status) => status.state.to_owned(),
         None => MonitorState::NoData,
     };
+    let was_flapping = monitor.status().and_then(|s| s.flapping).unwrap_or(false);
+    let notified_state = monitor
+        .status()
+        .and_then(|s| s.notified_state.clone())
+        .unwrap_or_else(|| old_state.clone());
 
-    let check_result = monitor.check().await;
+    let ProbeResult {
+        result: check_result,
+        attempts,
+    } = probe_result;
+    let result_state = check_result.state.clone();
 
-    let new_state = match check_result {
-        Ok(state) => state,
-        Err(e) => {
-            error!("Check failed for {}: {:?}", monitor.name_any(), e);
-            MonitorState::NoData
-        }
+    let now = Utc::now();
+    let transition = evaluate_transition(config, monitor.status(), &result_state, now);
+    let new_state = transition.state.clone();
+    if new_state != result_state {
+        info!(
+            "Holding {} at {:?} (check returned {:?}, {} consecutive failures, {} consecutive successes)",
+            name,
+            new_state,
+            result_state,
+            transition.consecutive_failures,
+            transition.consecutive_successes
+        );
+    }
+
+    // Notifiers only hear about the state once the monitor has stopped flapping
+    let new_notified_state = if transition.flapping {
+        notified_state.clone()
+    } else {
+        new_state.clone()
     };
 
+    let last_transition_time = match monitor.status() {
+        Some(status) if status.state == new_state => status
+            .last_transition_time
+            .clone()
+            .unwrap_or_else(|| now.to_rfc3339()),
+        _ => now.to_rfc3339(),
+    };
+    let conditions = build_conditions(
+        monitor.status().and_then(|s| s.conditions.as_deref()),
+        &new_state,
+        transition.flapping,
+        check_result.message.as_deref(),
+        now,
+    );
+
     // Update Status
     let status = serde_json::json!({
         "status": {
-            "last_checked": chrono::Utc::now().to_rfc3339(),
-            "state": new_state
+            "last_checked": now.to_rfc3339(),
+            "state": new_state,
+            "last_transition_time": last_transition_time,
+            "message": check_result.message,
+            "response_time_ms": check_result.response_time_ms,
+            "observed_generation": monitor.meta().generation,
+            "conditions": conditions,
+            "attempts": attempts,
+            "consecutive_failures": transition.consecutive_failures,
+            "consecutive_successes": transition.consecutive_successes,
+            "recent_transitions": transition.recent_transitions,
+            "flapping": transition.flapping,
+            "notified_state": new_notified_state,
+            "locations": locations
         }
     });
 
@@ -48,46 +612,464 @@ where
         Err(e) => error!("Failed to update status for {}: {:?}", name, e),
     }
 
+    let labels = monitor_labels(&T::kind(&()), &ns, &name);
+    METRICS.touch_monitor(&labels, metrics_ttl(monitor.monitor_config()));
+    let up = matches!(new_state, MonitorState::Healthy | MonitorState::Warning);
+    METRICS
+        .monitor_up
+        .set(labels.clone(), if up { 1.0 } else { 0.0 });
+
     // Emit event if state changed
     if old_state != new_state {
-        let reason = "StateChange";
-        let message = format!(
-            "Monitor state changed from {:?} to {:?}",
-            old_state, new_state
-        );
-        let type_ = match new_state {
-            MonitorState::Healthy => "Normal",
-            _ => "Warning",
-        };
+        let mut transition_labels = labels;
+        transition_labels.push(("from", format!("{:?}", old_state)));
+        transition_labels.push(("to", format!("{:?}", new_state)));
+        METRICS.state_transitions.inc(transition_labels);
 
-        let kind = T::kind(&());
-        let api_version = T::api_version(&());
+        if new_state == MonitorState::Error {
+            let message = format!(
+                "Monitor could not perform its check: {}",
+                check_result.message.as_deref().unwrap_or("unknown error")
+            );
+            emit_event::<T>(
+                client.clone(),
+                &name,
+                &ns,
+                "CheckError",
+                &message,
+                "Warning",
+            )
+            .await;
+        } else {
+            let message = format!(
+                "Monitor state changed from {:?} to {:?}",
+                old_state, new_state
+            );
+            let type_ = match new_state {
+                MonitorState::Healthy => "Normal",
+                _ => "Warning",
+            };
+            emit_event::<T>(client.clone(), &name, &ns, "StateChange", &message, type_).await;
+        }
+    }
 
-        if let Err(e) = crate::shared::resources::common::publish_event(
+    if transition.flapping && !was_flapping {
+        let message = format!(
+            "Monitor changed state {} times within the flap detection window, suppressing notifications",
+            transition.recent_transitions.len()
+        );
+        emit_event::<T>(
             client.clone(),
             &name,
-            &kind,
-            &api_version,
             &ns,
-            reason,
+            "FlappingStarted",
             &message,
-            type_,
+            "Warning",
         )
-        .await
-        {
-            error!("Failed to publish event for {}: {:?}", name, e);
+        .await;
+    } else if !transition.flapping && was_flapping {
+        let message = format!("Monitor stopped flapping in state {:?}", new_state);
+        emit_event::<T>(
+            client.clone(),
+            &name,
+            &ns,
+            "FlappingStopped",
+            &message,
+            "Normal",
+        )
+        .await;
+    }
+
+    if transition.flapping {
+        info!("Suppressing notifications for {} while flapping", name);
+        return;
+    }
+
+    // Process notifications. Transitions into or out of Error are about the monitor itself,
+    // so they go to the error notifiers when those are configured.
+    let involves_error = notified_state == MonitorState::Error || new_state == MonitorState::Error;
+    let match_labels = match &config.error_notifiers_match_labels {
+        Some(_) if involves_error => &config.error_notifiers_match_labels,
+        _ => &config.notifiers_match_labels,
+    };
+    let notification = notifiers::Notification {
+        monitor_name: name,
+        monitor_namespace: ns,
+        monitor_kind: T::kind(&()).to_string(),
+        target: monitor.target(),
+        old_state: notified_state,
+        new_state,
+        message: check_result.message,
+        response_time_ms: check_result.response_time_ms,
+        timestamp: now.to_rfc3339(),
+    };
+    notifiers::process_notifications(client, match_labels, &notification).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::resources::common::FlapDetectionSpec;
+    use crate::shared::resources::monitors::http_monitor::v1alpha2::HTTPMonitor;
+    use crate::shared::resources::monitors::tcp_monitor::v1alpha1::{TCPMonitor, TCPMonitorSpec};
+
+    fn tcp_monitor(port: u16, retries: u32) -> TCPMonitor {
+        TCPMonitor::new(
+            "test-monitor",
+            TCPMonitorSpec {
+                host: "127.0.0.1".to_string(),
+                port,
+                warning_latency_ms: None,
+                critical_latency_ms: None,
+                monitor_config: MonitorConfigSpec {
+                    timeout: 1,
+                    retries,
+                    retry_delay: Some(0),
+                    polling_frequency: 10,
+                    notifiers_match_labels: None,
+                    ..Default::default()
+                },
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_check_with_retries_exhausts_retries_on_failure() {
+        // Bind and drop a listener to get a port nothing is listening on
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let (result, attempts) = check_with_retries(&tcp_monitor(port, 2), None).await;
+
+        assert_eq!(result.unwrap().state, MonitorState::Critical);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_check_with_retries_stops_on_success() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let (result, attempts) = check_with_retries(&tcp_monitor(port, 2), None).await;
+
+        assert_eq!(result.unwrap().state, MonitorState::Healthy);
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_with_retries_stays_within_polling_frequency() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let mut monitor = tcp_monitor(port, 5);
+        monitor.spec.monitor_config.polling_frequency = 3;
+        monitor.spec.monitor_config.retry_delay = Some(1);
+        monitor.spec.monitor_config.retry_backoff = Some(2);
+
+        let (result, attempts) = check_with_retries(&monitor, None).await;
+
+        // The third attempt would end after 1s + 2s delays and the 1s timeout
+        assert_eq!(result.unwrap().state, MonitorState::Critical);
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_check_with_retries_does_not_retry_errors() {
+        let monitor: HTTPMonitor = serde_json::from_value(serde_json::json!({
+            "apiVersion": "kastlewatch.io/v1alpha2",
+            "kind": "HTTPMonitor",
+            "metadata": { "name": "api", "namespace": "default" },
+            "spec": {
+                "url": "http://127.0.0.1:1/health",
+                "method": "GET",
+                "bearer_token": { "token_secret_ref": { "name": "api-token", "key": "token" } },
+                "monitor_config": { "timeout": 1, "retries": 3, "retry_delay": 0, "polling_frequency": 60 }
+            }
+        }))
+        .unwrap();
+
+        let (result, attempts) = check_with_retries(&monitor, None).await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_with_retries_slow_connection_is_warning() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut monitor = tcp_monitor(port, 2);
+        monitor.spec.warning_latency_ms = Some(0);
+
+        let (result, attempts) = check_with_retries(&monitor, None).await;
+
+        // Slow but up is not a failure, so it is not retried
+        let result = result.unwrap();
+        assert_eq!(result.state, MonitorState::Warning);
+        assert!(result.response_time_ms.is_some());
+        assert_eq!(attempts, 1);
+    }
+
+    fn config(
+        failure_threshold: Option<u32>,
+        success_threshold: Option<u32>,
+        flap_detection: Option<FlapDetectionSpec>,
+    ) -> MonitorConfigSpec {
+        MonitorConfigSpec {
+            failure_threshold,
+            success_threshold,
+            flap_detection,
+            ..tcp_monitor(0, 0).spec.monitor_config
         }
     }
 
-    // Process notifications
-    let config = monitor.monitor_config();
-    notifiers::process_notifications(
-        client,
-        &name,
-        &ns,
-        &config.notifiers_match_labels,
-        &old_state,
-        &new_state,
-    )
-    .await;
+    fn status(state: MonitorState, failures: u32, successes: u32) -> MonitorStatus {
+        MonitorStatus {
+            state,
+            consecutive_failures: Some(failures),
+            consecutive_successes: Some(successes),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_evaluate_transition_first_result_is_adopted() {
+        let t = evaluate_transition(
+            &config(Some(3), None, None),
+            None,
+            &MonitorState::Critical,
+            Utc::now(),
+        );
+        assert_eq!(t.state, MonitorState::Critical);
+        assert_eq!(t.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn test_evaluate_transition_error_bypasses_thresholds() {
+        let config = config(Some(3), Some(3), None);
+        let previous = status(MonitorState::Healthy, 0, 5);
+        let t = evaluate_transition(&config, Some(&previous), &MonitorState::Error, Utc::now());
+        assert_eq!(t.state, MonitorState::Error);
+
+        let previous = status(MonitorState::Error, 0, 5);
+        let t = evaluate_transition(
+            &config,
+            Some(&previous),
+            &MonitorState::Critical,
+            Utc::now(),
+        );
+        assert_eq!(t.state, MonitorState::Critical);
+    }
+
+    #[test]
+    fn test_evaluate_transition_failure_threshold() {
+        let config = config(Some(3), None, None);
+        let previous = status(MonitorState::Healthy, 1, 0);
+        let t = evaluate_transition(
+            &config,
+            Some(&previous),
+            &MonitorState::Critical,
+            Utc::now(),
+        );
+        assert_eq!(t.state, MonitorState::Healthy);
+        assert_eq!(t.consecutive_failures, 2);
+
+        let previous = status(MonitorState::Healthy, 2, 0);
+        let t = evaluate_transition(
+            &config,
+            Some(&previous),
+            &MonitorState::Critical,
+            Utc::now(),
+        );
+        assert_eq!(t.state, MonitorState::Critical);
+        assert_eq!(t.consecutive_successes, 0);
+    }
+
+    #[test]
+    fn test_evaluate_transition_success_threshold() {
+        let config = config(None, Some(2), None);
+        let previous = status(MonitorState::Critical, 5, 0);
+        let t = evaluate_transition(&config, Some(&previous), &MonitorState::Healthy, Utc::now());
+        assert_eq!(t.state, MonitorState::Critical);
+        assert_eq!(t.consecutive_failures, 0);
+        assert_eq!(t.consecutive_successes, 1);
+
+        let previous = status(MonitorState::Critical, 0, 1);
+        let t = evaluate_transition(&config, Some(&previous), &MonitorState::Healthy, Utc::now());
+        assert_eq!(t.state, MonitorState::Healthy);
+    }
+
+    #[test]
+    fn test_evaluate_transition_flapping() {
+        let config = config(
+            None,
+            None,
+            Some(FlapDetectionSpec {
+                max_transitions: 3,
+                window: 60,
+            }),
+        );
+        let now = Utc::now();
+        let mut previous = status(MonitorState::Healthy, 0, 3);
+        previous.recent_transitions = Some(vec![
+            (now - chrono::Duration::seconds(120)).to_rfc3339(),
+            (now - chrono::Duration::seconds(30)).to_rfc3339(),
+            (now - chrono::Duration::seconds(20)).to_rfc3339(),
+        ]);
+
+        let t = evaluate_transition(&config, Some(&previous), &MonitorState::Critical, now);
+        assert_eq!(t.state, MonitorState::Critical);
+        assert_eq!(t.recent_transitions.len(), 3);
+        assert!(t.flapping);
+
+        // Without a transition the old entries age out of the window
+        let t = evaluate_transition(
+            &config,
+            Some(&previous),
+            &MonitorState::Healthy,
+            now + chrono::Duration::seconds(35),
+        );
+        assert_eq!(t.recent_transitions.len(), 1);
+        assert!(!t.flapping);
+    }
+
+    #[test]
+    fn test_build_conditions() {
+        let earlier = (Utc::now() - chrono::Duration::seconds(60)).to_rfc3339();
+        let previous = build_conditions(
+            None,
+            &MonitorState::Healthy,
+            false,
+            None,
+            DateTime::parse_from_rfc3339(&earlier)
+                .unwrap()
+                .with_timezone(&Utc),
+        );
+
+        let conditions = build_conditions(
+            Some(&previous),
+            &MonitorState::Critical,
+            false,
+            Some("connection refused"),
+            Utc::now(),
+        );
+
+        let ready = conditions.iter().find(|c| c.type_ == "Ready").unwrap();
+        assert_eq!(ready.status, "False");
+        assert_eq!(ready.reason.as_deref(), Some("Critical"));
+        assert_eq!(ready.message.as_deref(), Some("connection refused"));
+        assert_ne!(
+            ready.last_transition_time.as_deref(),
+            Some(earlier.as_str())
+        );
+
+        // Degraded did not change, so it keeps its transition time
+        let degraded = conditions.iter().find(|c| c.type_ == "Degraded").unwrap();
+        assert_eq!(degraded.status, "False");
+        assert_eq!(
+            degraded.last_transition_time.as_deref(),
+            Some(earlier.as_str())
+        );
+    }
+
+    #[test]
+    fn test_build_conditions_clears_worker_unreachable() {
+        let now = Utc::now();
+        let mut previous = build_conditions(None, &MonitorState::Healthy, false, None, now);
+        set_condition(
+            &mut previous,
+            WORKER_UNREACHABLE_CONDITION,
+            "True",
+            "DispatchFailed",
+            Some("connection refused"),
+            now,
+        );
+
+        let conditions =
+            build_conditions(Some(&previous), &MonitorState::Healthy, false, None, now);
+
+        assert_eq!(conditions.len(), 3);
+        let unreachable = conditions
+            .iter()
+            .find(|c| c.type_ == WORKER_UNREACHABLE_CONDITION)
+            .unwrap();
+        assert_eq!(unreachable.status, "False");
+        assert_eq!(unreachable.reason.as_deref(), Some("WorkerReachable"));
+    }
+
+    fn location(name: &str, state: MonitorState, response_time_ms: u64) -> (String, ProbeResult) {
+        let message = format!("{:?} from {}", state, name);
+        (
+            name.to_string(),
+            ProbeResult {
+                result: CheckResult {
+                    state,
+                    message: Some(message),
+                    response_time_ms: Some(response_time_ms),
+                    status_code: None,
+                },
+                attempts: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn test_aggregate_locations_quorum() {
+        // A single failing location out of three is outvoted
+        let results = vec![
+            location("eu", MonitorState::Critical, 900),
+            location("us", MonitorState::Healthy, 20),
+            location("ap", MonitorState::Healthy, 30),
+        ];
+        let aggregated = aggregate_locations(&results, None);
+        assert_eq!(aggregated.result.state, MonitorState::Healthy);
+        assert_eq!(aggregated.result.response_time_ms, Some(30));
+        assert_eq!(
+            aggregated.result.message.as_deref(),
+            Some("Healthy at 2/3 locations (eu: Critical from eu)")
+        );
+
+        // Two out of three reach the default majority quorum
+        let results = vec![
+            location("eu", MonitorState::Critical, 900),
+            location("us", MonitorState::Critical, 800),
+            location("ap", MonitorState::Healthy, 30),
+        ];
+        let aggregated = aggregate_locations(&results, None);
+        assert_eq!(aggregated.result.state, MonitorState::Critical);
+
+        // A quorum of 1 alarms on any location
+        let results = vec![
+            location("eu", MonitorState::Warning, 900),
+            location("us", MonitorState::Healthy, 20),
+        ];
+        let aggregated = aggregate_locations(&results, Some(1));
+        assert_eq!(aggregated.result.state, MonitorState::Warning);
+    }
+
+    #[test]
+    fn test_aggregate_locations_without_votes() {
+        let results = vec![location("eu", MonitorState::Error, 0)];
+        let aggregated = aggregate_locations(&results, None);
+        assert_eq!(aggregated.result.state, MonitorState::Error);
+
+        // Locations that could not check do not count towards the default majority
+        let results = vec![
+            location("eu", MonitorState::Error, 0),
+            location("us", MonitorState::Critical, 20),
+        ];
+        let aggregated = aggregate_locations(&results, None);
+        assert_eq!(aggregated.result.state, MonitorState::Critical);
+
+        // Nor is an explicit quorum lowered to the locations left
+        let aggregated = aggregate_locations(&results, Some(2));
+        assert_eq!(aggregated.result.state, MonitorState::Error);
+        assert_eq!(
+            aggregated.result.message.as_deref(),
+            Some(
+                "Not enough locations: 1 of 2 needed for the quorum returned a result (eu: Error from eu)"
+            )
+        );
+    }
 }
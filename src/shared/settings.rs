@@ -1,21 +1,172 @@
 use config::{Config, ConfigError, File};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct Settings {
     pub controller: ControllerSettings,
     pub worker: WorkerSettings,
+    /// Shared secret authenticating controller-to-worker requests. Optional. If not defined, requests are not signed.
+    #[serde(default)]
+    pub auth: Option<AuthSettings>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct ControllerSettings {
     pub base_url: String,
+    /// Whether workers only probe and return results, leaving status writes, events and notifications to the controller.
+    /// Optional. Defaults to false. Must match the worker's probe_only setting.
+    /// Monitors reading Secrets or ConfigMaps, such as HTTPMonitors with a bearer_token, are rejected in this mode.
+    pub probe_only: Option<bool>,
+    /// Worker locations to probe from. Optional. If defined, each monitor is probed from its selected locations
+    /// and the controller records the combined result. Otherwise the single worker at base_url is used.
+    pub locations: Option<Vec<LocationSettings>>,
+    /// Service of the worker replicas. Optional. If defined, monitors are spread over the ready replicas
+    /// by consistent hashing instead of going through base_url. Not used with locations.
+    pub worker_service: Option<WorkerServiceSettings>,
+    /// Lease-based leader election between controller replicas. Optional. If not defined, every replica dispatches checks.
+    pub leader_election: Option<LeaderElectionSettings>,
+    /// Port of the controller's /healthz and /readyz probes and /metrics. Optional. If not defined, no probe server is started.
+    pub probe_port: Option<u16>,
+    /// TLS settings for reaching the worker. Optional.
+    #[serde(default)]
+    pub tls: Option<ClientTlsSettings>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct WorkerSettings {
     pub port: u16,
     pub host: String,
+    /// Maximum number of checks run at once. Optional. Defaults to 64.
+    pub max_concurrency: Option<usize>,
+    /// Maximum number of checks waiting for a free slot. Optional. Defaults to 256.
+    pub queue_size: Option<usize>,
+    /// Maximum number of checks in flight against a single host. Optional. Defaults to 4.
+    pub max_per_host: Option<usize>,
+    /// Whether the worker runs without Kubernetes credentials and only answers probe requests. Optional. Defaults to false.
+    pub probe_only: Option<bool>,
+    /// TLS settings for the worker server. Optional. If not defined, the worker serves plain HTTP.
+    #[serde(default)]
+    pub tls: Option<ServerTlsSettings>,
+    /// Modules of the blackbox_exporter compatible /probe endpoint, by name. Optional.
+    /// If not defined, the endpoint is not served.
+    #[serde(default)]
+    pub modules: Option<BTreeMap<String, ProbeModuleSettings>>,
+    /// Who may use the /probe endpoint and what it may reach. Optional. If not defined, anyone who can
+    /// reach the worker can make it connect to any target.
+    #[serde(default)]
+    pub probe_access: Option<ProbeAccessSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProbeAccessSettings {
+    /// File holding a token scrapers must send as "Authorization: Bearer <token>", e.g. a mounted Secret. Optional.
+    pub bearer_token_file: Option<String>,
+    /// Targets that may be probed: IP addresses, CIDR ranges such as "10.0.0.0/8", host names,
+    /// or "*.example.com" for subdomains. Optional. If not defined, any target can be probed.
+    pub allowed_targets: Option<Vec<String>>,
+}
+
+/// The check a probe module runs
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Prober {
+    Tcp,
+    Http,
+    Dns,
+    Tls,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProbeModuleSettings {
+    /// The check to run: tcp, http, dns or tls. Required unless monitor is defined.
+    pub prober: Option<Prober>,
+    /// Timeout in seconds. Optional. Defaults to the referenced monitor's timeout, or 5.
+    pub timeout: Option<u32>,
+    /// Spec fields of the matching monitor kind, without the target, e.g. method and status_code for http
+    /// or name and record_type for dns. Optional.
+    pub spec: Option<serde_json::Value>,
+    /// An existing monitor whose spec is reused with the target replaced. Optional.
+    /// Its headers, basic_auth, bearer_token and TLS client certificate are not sent, as the caller picks the target.
+    pub monitor: Option<MonitorRefSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MonitorRefSettings {
+    /// Kind of the monitor, e.g. HTTPMonitor
+    pub kind: String,
+    /// Namespace of the monitor
+    pub namespace: String,
+    /// Name of the monitor
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LeaderElectionSettings {
+    /// Namespace of the Lease
+    pub namespace: String,
+    /// Name of the Lease. Optional. Defaults to kastlewatch-controller.
+    pub lease_name: Option<String>,
+    /// Seconds a standby waits after the last renewal before taking over. Optional. Defaults to 15.
+    pub lease_duration: Option<u64>,
+    /// Seconds between renewals of the lease. Optional. Defaults to a third of the lease duration.
+    pub renew_period: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WorkerServiceSettings {
+    /// Namespace of the worker Service
+    pub namespace: String,
+    /// Name of the worker Service
+    pub name: String,
+    /// Port of the worker on each replica. Optional. Defaults to the first port of the EndpointSlice.
+    pub port: Option<u16>,
+    /// Scheme used to reach the replicas. Optional. Defaults to http.
+    pub scheme: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LocationSettings {
+    /// The name monitors select the location by
+    pub name: String,
+    /// Base URL of the worker at this location
+    pub base_url: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthSettings {
+    /// File holding the shared key, e.g. a mounted Secret. Optional. Required for workers in probe-only mode.
+    pub key_file: Option<String>,
+    /// Namespace of the Secret holding the shared key. Used when key_file is not defined.
+    pub secret_namespace: Option<String>,
+    /// Name of the Secret holding the shared key. Used when key_file is not defined.
+    pub secret_name: Option<String>,
+    /// Key within the Secret. Used when key_file is not defined.
+    pub secret_key: Option<String>,
+    /// Maximum age in seconds of a signed request. Optional. Defaults to 60.
+    /// Replayed requests are only detected per worker replica, keep this small.
+    #[serde(default)]
+    pub max_skew: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClientTlsSettings {
+    /// PEM file with the CA the worker certificate is verified against. Optional. Defaults to the system roots.
+    pub ca_file: Option<String>,
+    /// PEM file with the controller's client certificate, for mTLS. Optional.
+    pub cert_file: Option<String>,
+    /// PEM file with the controller's private key (PKCS#8, PKCS#1 or SEC1), for mTLS. Optional.
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServerTlsSettings {
+    /// PEM file with the worker's certificate chain
+    pub cert_file: String,
+    /// PEM file with the worker's private key
+    pub key_file: String,
+    /// PEM file with the CA client certificates must be signed by. Optional. If defined, clients must present a certificate.
+    pub client_ca_file: Option<String>,
 }
 
 impl Settings {
@@ -0,0 +1,152 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Points on the ring per endpoint, so load spreads evenly with few replicas
+const VIRTUAL_NODES: u32 = 128;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a, which unlike DefaultHasher gives the same assignment across Rust releases,
+/// so controller replicas built with different toolchains agree on it. FNV-1a barely changes the
+/// high bits for keys that differ in their last bytes, so the MurmurHash3 finalizer spreads them.
+fn hash_of(value: &str) -> u64 {
+    let mut hash = value.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Consistent hash ring of worker endpoints.
+/// Adding or removing an endpoint only moves the monitors that hashed to it.
+#[derive(Debug, Default)]
+pub struct HashRing {
+    ring: BTreeMap<u64, String>,
+    endpoints: BTreeSet<String>,
+}
+
+impl HashRing {
+    pub fn new(endpoints: BTreeSet<String>) -> Self {
+        let mut ring = BTreeMap::new();
+        for endpoint in &endpoints {
+            for i in 0..VIRTUAL_NODES {
+                ring.insert(hash_of(&format!("{}#{}", endpoint, i)), endpoint.clone());
+            }
+        }
+        HashRing { ring, endpoints }
+    }
+
+    /// Returns the endpoint owning the key, or None if there are no endpoints
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let hash = hash_of(key);
+        self.ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, endpoint)| endpoint.as_str())
+    }
+
+    pub fn endpoints(&self) -> &BTreeSet<String> {
+        &self.endpoints
+    }
+}
+
+/// Worker replicas discovered from the worker Service, assigned to monitors by consistent hashing
+#[derive(Debug, Default)]
+pub struct WorkerShards {
+    ring: RwLock<HashRing>,
+    /// Set once the worker endpoints were listed, an empty ring before that does not mean there are no workers
+    synced: AtomicBool,
+}
+
+impl WorkerShards {
+    /// Returns the base URL of the worker replica the monitor is assigned to
+    pub fn worker_for(&self, key: &str) -> Option<String> {
+        self.ring.read().unwrap().get(key).map(str::to_string)
+    }
+
+    /// Whether the worker replicas were listed yet
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Acquire)
+    }
+
+    /// Replaces the set of worker replicas, returning whether it changed
+    pub fn update(&self, endpoints: BTreeSet<String>) -> bool {
+        let mut ring = self.ring.write().unwrap();
+        self.synced.store(true, Ordering::Release);
+        if *ring.endpoints() == endpoints {
+            return false;
+        }
+        *ring = HashRing::new(endpoints);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_hash_ring_moves_few_keys() {
+        let keys: Vec<String> = (0..1000)
+            .map(|i| format!("TCPMonitor/default/m{}", i))
+            .collect();
+        let before = HashRing::new(endpoints(&["a", "b", "c"]));
+        let after = HashRing::new(endpoints(&["a", "b", "c", "d"]));
+
+        let mut moved = 0;
+        let mut per_endpoint = BTreeMap::new();
+        for key in &keys {
+            let old = before.get(key).unwrap();
+            let new = after.get(key).unwrap();
+            if old != new {
+                // Keys only ever move to the new endpoint
+                assert_eq!(new, "d");
+                moved += 1;
+            }
+            *per_endpoint.entry(old).or_insert(0) += 1;
+        }
+        assert!(moved > 100 && moved < 400, "moved {} keys", moved);
+        // Load is roughly even
+        assert!(per_endpoint.values().all(|count| *count > 200));
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        // The assignment of monitors must not change between builds
+        assert_eq!(hash_of(""), 0xefd01f60ba992926);
+        assert_eq!(hash_of("a"), 0x82a2a958a9bece5b);
+        assert_eq!(hash_of("foobar"), 0x2c22194922d1672b);
+    }
+
+    #[test]
+    fn test_empty_ring() {
+        let ring = HashRing::new(BTreeSet::new());
+        assert_eq!(ring.get("TCPMonitor/default/m"), None);
+    }
+
+    #[test]
+    fn test_worker_shards_synced() {
+        let shards = WorkerShards::default();
+        assert!(!shards.is_synced());
+        assert_eq!(shards.worker_for("TCPMonitor/default/m"), None);
+
+        // Listing no replicas is still a sync
+        assert!(!shards.update(BTreeSet::new()));
+        assert!(shards.is_synced());
+        assert!(shards.update(endpoints(&["http://10.0.0.1:3000"])));
+        assert_eq!(
+            shards.worker_for("TCPMonitor/default/m").as_deref(),
+            Some("http://10.0.0.1:3000")
+        );
+    }
+}
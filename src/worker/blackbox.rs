@@ -0,0 +1,386 @@
+use crate::shared::auth::ProbeAccess;
+use crate::shared::context::AppState;
+use crate::shared::resources::common::{CheckResult, MonitorResource, MonitorState};
+use crate::shared::resources::monitors::dns_monitor::v1alpha1::DNSMonitor;
+use crate::shared::resources::monitors::http_monitor::v1alpha2::HTTPMonitor;
+use crate::shared::resources::monitors::tcp_monitor::v1alpha1::TCPMonitor;
+use crate::shared::resources::monitors::tls_certificate_monitor::v1alpha1::TLSCertificateMonitor;
+use crate::shared::resources::worker;
+use crate::shared::settings::{MonitorRefSettings, ProbeModuleSettings, Prober};
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use kube::{Api, Client, Resource};
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt::Write;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const DEFAULT_TIMEOUT: u32 = 5;
+/// blackbox_exporter answers in the Prometheus text format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Deserialize, Debug)]
+pub struct ProbeParams {
+    pub module: Option<String>,
+    pub target: Option<String>,
+}
+
+impl Prober {
+    fn for_kind(kind: &str) -> Option<Self> {
+        [Prober::Tcp, Prober::Http, Prober::Dns, Prober::Tls]
+            .into_iter()
+            .find(|prober| prober.kind() == kind)
+    }
+
+    fn kind(&self) -> std::borrow::Cow<'static, str> {
+        match self {
+            Prober::Tcp => TCPMonitor::kind(&()),
+            Prober::Http => HTTPMonitor::kind(&()),
+            Prober::Dns => DNSMonitor::kind(&()),
+            Prober::Tls => TLSCertificateMonitor::kind(&()),
+        }
+    }
+}
+
+/// Splits a "host:port" target, accepting bracketed IPv6 addresses
+fn split_host_port(target: &str) -> anyhow::Result<(String, u16)> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow::anyhow!("target {} must be host:port", target))?;
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| anyhow::anyhow!("invalid port in target {}", target))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host.to_string(), port))
+}
+
+/// Fills the target and timeout into the spec of a module.
+/// The target is a URL for http, host:port for tcp and tls, and the nameserver for dns.
+pub fn build_spec(
+    prober: Prober,
+    mut spec: serde_json::Value,
+    target: &str,
+    timeout: Option<u32>,
+) -> anyhow::Result<serde_json::Value> {
+    let fields = spec
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("module spec must be a table"))?;
+    match prober {
+        Prober::Tcp | Prober::Tls => {
+            let (host, port) = split_host_port(target)?;
+            fields.insert("host".to_string(), json!(host));
+            fields.insert("port".to_string(), json!(port));
+        }
+        Prober::Http => {
+            let url = if target.contains("://") {
+                target.to_string()
+            } else {
+                format!("http://{}", target)
+            };
+            fields.insert("url".to_string(), json!(url));
+            fields.entry("method").or_insert(json!("GET"));
+        }
+        Prober::Dns => {
+            fields.insert("nameserver".to_string(), json!(target));
+        }
+    }
+
+    // A probe is a single attempt, the scraper decides how often to run it
+    let timeout = timeout
+        .or_else(|| {
+            fields
+                .get("monitor_config")
+                .and_then(|c| c.get("timeout"))
+                .and_then(|t| t.as_u64())
+                .map(|t| t as u32)
+        })
+        .unwrap_or(DEFAULT_TIMEOUT);
+    fields.insert(
+        "monitor_config".to_string(),
+        json!({ "timeout": timeout, "retries": 0, "polling_frequency": 0 }),
+    );
+    Ok(spec)
+}
+
+/// Encodes the result like blackbox_exporter does
+pub fn encode_result(result: &CheckResult, duration: Duration) -> String {
+    let success = matches!(result.state, MonitorState::Healthy | MonitorState::Warning);
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, value: String| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+    gauge(
+        "probe_success",
+        "Displays whether or not the probe was a success",
+        (success as u8).to_string(),
+    );
+    gauge(
+        "probe_duration_seconds",
+        "Returns how long the probe took to complete in seconds",
+        duration.as_secs_f64().to_string(),
+    );
+    if let Some(status_code) = result.status_code {
+        gauge(
+            "probe_http_status_code",
+            "Response HTTP status code",
+            status_code.to_string(),
+        );
+    }
+    out
+}
+
+/// Reads the spec of an existing monitor
+async fn monitor_spec<T>(
+    client: Client,
+    monitor: &MonitorRefSettings,
+) -> anyhow::Result<serde_json::Value>
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let api: Api<T> = Api::namespaced(client, &monitor.namespace);
+    let resource = api.get(&monitor.name).await?;
+    Ok(serde_json::to_value(&resource)?["spec"].take())
+}
+
+/// Removes the credentials of a monitor's spec, as /probe sends the request to a target the caller picks
+fn strip_credentials(spec: &mut serde_json::Value) {
+    let Some(fields) = spec.as_object_mut() else {
+        return;
+    };
+    for field in ["headers", "basic_auth", "bearer_token"] {
+        fields.remove(field);
+    }
+    if let Some(tls) = fields.get_mut("tls").and_then(|tls| tls.as_object_mut()) {
+        tls.remove("client_cert_secret_ref");
+        tls.remove("client_key_secret_ref");
+    }
+}
+
+/// Resolves the module to the prober and spec it runs
+async fn module_spec(
+    state: &AppState,
+    module: &ProbeModuleSettings,
+) -> anyhow::Result<(Prober, serde_json::Value)> {
+    let Some(monitor) = &module.monitor else {
+        let prober = module
+            .prober
+            .ok_or_else(|| anyhow::anyhow!("module must define a prober or a monitor"))?;
+        return Ok((prober, module.spec.clone().unwrap_or_else(|| json!({}))));
+    };
+
+    let prober = Prober::for_kind(&monitor.kind)
+        .ok_or_else(|| anyhow::anyhow!("unsupported monitor kind {}", monitor.kind))?;
+    let client = state
+        .client
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("monitor modules need Kubernetes credentials"))?;
+    let mut spec = match prober {
+        Prober::Tcp => monitor_spec::<TCPMonitor>(client, monitor).await?,
+        Prober::Http => monitor_spec::<HTTPMonitor>(client, monitor).await?,
+        Prober::Dns => monitor_spec::<DNSMonitor>(client, monitor).await?,
+        Prober::Tls => monitor_spec::<TLSCertificateMonitor>(client, monitor).await?,
+    };
+    strip_credentials(&mut spec);
+    Ok((prober, spec))
+}
+
+/// Runs `check` on a monitor built from the spec, answering with blackbox_exporter metrics
+async fn run_probe<T>(
+    state: AppState,
+    namespace: Option<&str>,
+    spec: serde_json::Value,
+    check: impl AsyncFnOnce(&T, Option<Client>) -> anyhow::Result<CheckResult>,
+) -> Response
+where
+    T: MonitorResource + serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let monitor: T = match serde_json::from_value(json!({
+        "apiVersion": T::api_version(&()),
+        "kind": T::kind(&()),
+        "metadata": { "name": "probe", "namespace": namespace },
+        "spec": spec,
+    })) {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("invalid module: {}", e)).into_response();
+        }
+    };
+    if let Err(e) = monitor.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    if !state.probe_access.allows(&monitor.target_host()) {
+        warn!(
+            "Rejected probe of {}, not an allowed target",
+            monitor.target()
+        );
+        return (
+            StatusCode::FORBIDDEN,
+            format!("Target {} is not allowed", monitor.target_host()),
+        )
+            .into_response();
+    }
+
+    let ticket = match state.queue.try_admit(&monitor.target_host()) {
+        Ok(ticket) => ticket,
+        Err(rejection) => return worker::rejected(&state, &monitor, rejection),
+    };
+
+    info!("Probing {} {}", T::kind(&()), monitor.target());
+    let start = Instant::now();
+    let result = match ticket.run(check(&monitor, state.client.clone())).await {
+        Ok(result) => result,
+        Err(e) => CheckResult::new(MonitorState::Error, e.to_string()),
+    };
+    let duration = start.elapsed();
+    if !matches!(result.state, MonitorState::Healthy | MonitorState::Warning) {
+        warn!(
+            "Probe of {} failed: {}",
+            monitor.target(),
+            result.message.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    (
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        encode_result(&result, duration),
+    )
+        .into_response()
+}
+
+/// Handler for /probe?module=...&target=..., compatible with blackbox_exporter.
+/// The module picks the check from the worker settings, the target is what it checks.
+pub async fn handle_probe(
+    State(state): State<AppState>,
+    Query(params): Query<ProbeParams>,
+) -> Response {
+    let Some(target) = params.target.filter(|t| !t.is_empty()) else {
+        return (StatusCode::BAD_REQUEST, "Target parameter is missing").into_response();
+    };
+    let module_name = params.module.unwrap_or_else(|| "http_2xx".to_string());
+    let Some(module) = state.modules.get(&module_name).cloned() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown module \"{}\"", module_name),
+        )
+            .into_response();
+    };
+
+    let (prober, spec) = match module_spec(&state, &module).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("module {}: {}", module_name, e),
+            )
+                .into_response();
+        }
+    };
+    let spec = match build_spec(prober, spec, &target, module.timeout) {
+        Ok(spec) => spec,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // Secrets referenced by a monitor's spec are read from the monitor's namespace
+    let namespace = module.monitor.as_ref().map(|m| m.namespace.as_str());
+    match prober {
+        Prober::Tcp => run_probe(state, namespace, spec, TCPMonitor::check).await,
+        Prober::Http => {
+            // The allowlist applies to every hop, not only the target
+            let redirect = ProbeAccess::redirect_policy(state.probe_access.clone());
+            let check =
+                async |monitor: &HTTPMonitor, client| monitor.check_with(client, redirect).await;
+            run_probe(state, namespace, spec, check).await
+        }
+        Prober::Dns => run_probe(state, namespace, spec, DNSMonitor::check).await,
+        Prober::Tls => run_probe(state, namespace, spec, TLSCertificateMonitor::check).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_spec() {
+        let spec = build_spec(Prober::Tcp, json!({}), "[::1]:5432", None).unwrap();
+        assert_eq!(spec["host"], "::1");
+        assert_eq!(spec["port"], 5432);
+        assert_eq!(spec["monitor_config"]["timeout"], DEFAULT_TIMEOUT);
+
+        let spec = build_spec(
+            Prober::Http,
+            json!({ "status_code": [200], "monitor_config": { "timeout": 10 } }),
+            "example.com/health",
+            None,
+        )
+        .unwrap();
+        assert_eq!(spec["url"], "http://example.com/health");
+        assert_eq!(spec["method"], "GET");
+        assert_eq!(spec["monitor_config"]["timeout"], 10);
+        assert_eq!(spec["monitor_config"]["retries"], 0);
+
+        let spec = build_spec(
+            Prober::Dns,
+            json!({ "name": "example.com", "record_type": "A" }),
+            "1.1.1.1",
+            Some(2),
+        )
+        .unwrap();
+        assert_eq!(spec["nameserver"], "1.1.1.1");
+        assert_eq!(spec["monitor_config"]["timeout"], 2);
+
+        assert!(build_spec(Prober::Tcp, json!({}), "localhost", None).is_err());
+    }
+
+    #[test]
+    fn test_strip_credentials() {
+        let mut spec = json!({
+            "url": "https://api.example.com/health",
+            "method": "GET",
+            "headers": [{ "name": "X-Api-Key", "value_secret_ref": { "name": "api", "key": "key" } }],
+            "basic_auth": { "username": "probe", "password_secret_ref": { "name": "api", "key": "password" } },
+            "bearer_token": { "token_secret_ref": { "name": "api", "key": "token" } },
+            "tls": {
+                "ca_secret_ref": { "name": "api-ca", "key": "ca.crt" },
+                "client_cert_secret_ref": { "name": "api-client", "key": "tls.crt" },
+                "client_key_secret_ref": { "name": "api-client", "key": "tls.key" }
+            }
+        });
+        strip_credentials(&mut spec);
+
+        assert_eq!(spec["method"], "GET");
+        assert!(spec.get("headers").is_none());
+        assert!(spec.get("basic_auth").is_none());
+        assert!(spec.get("bearer_token").is_none());
+        assert_eq!(spec["tls"]["ca_secret_ref"]["name"], "api-ca");
+        assert!(spec["tls"].get("client_cert_secret_ref").is_none());
+        assert!(spec["tls"].get("client_key_secret_ref").is_none());
+
+        let spec = build_spec(Prober::Http, spec, "http://169.254.169.254/", None).unwrap();
+        assert!(
+            serde_json::from_value::<HTTPMonitor>(json!({
+                "apiVersion": "kastlewatch.io/v1alpha2",
+                "kind": "HTTPMonitor",
+                "metadata": { "name": "probe" },
+                "spec": spec,
+            }))
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_encode_result() {
+        let result =
+            CheckResult::new(MonitorState::Critical, "status 503 is not 2XX").with_status_code(503);
+        let text = encode_result(&result, Duration::from_millis(250));
+        assert!(text.contains("# TYPE probe_success gauge\nprobe_success 0\n"));
+        assert!(text.contains("probe_duration_seconds 0.25\n"));
+        assert!(text.contains("probe_http_status_code 503\n"));
+    }
+}
@@ -1,35 +1,156 @@
 use crate::shared::resources::common::MonitorResource;
+use crate::shared::resources::monitors::dns_monitor;
 use crate::shared::resources::monitors::http_monitor;
 use crate::shared::resources::monitors::tcp_monitor;
+use crate::shared::resources::monitors::tls_certificate_monitor;
+use crate::shared::resources::worker;
 use axum::{
     Router,
+    extract::State,
+    http::StatusCode,
+    middleware,
     routing::{get, post},
 };
+use axum_server::tls_openssl::{OpenSSLAcceptor, OpenSSLConfig};
 use kube::Client;
-use tracing::info;
+use std::sync::Arc;
+use tracing::{info, warn};
 
+use crate::shared::auth;
 use crate::shared::context::AppState;
+use crate::shared::metrics;
+use crate::shared::queue::WorkQueue;
+use crate::shared::settings::Settings;
+use crate::worker::blackbox;
 
-pub async fn run(client: Client, listener: tokio::net::TcpListener) -> anyhow::Result<()> {
+pub async fn run(
+    client: Option<Client>,
+    listener: tokio::net::TcpListener,
+    settings: Settings,
+) -> anyhow::Result<()> {
     let local_addr = listener.local_addr()?;
     info!("Starting Worker Server on {}", local_addr);
     // client is passed in
-    let state = AppState { client };
+    let dispatch_key = auth::load_dispatch_key(client.clone(), &settings).await?;
+    if dispatch_key.is_none() {
+        warn!("No auth settings configured, worker requests are not authenticated");
+    }
+    let state = AppState {
+        client,
+        dispatch_key,
+        queue: Arc::new(WorkQueue::from_settings(&settings.worker)),
+        modules: Arc::new(settings.worker.modules.clone().unwrap_or_default()),
+        probe_access: Arc::new(auth::ProbeAccess::load(
+            settings.worker.probe_access.as_ref(),
+        )?),
+    };
 
-    let app = Router::new()
-        .route("/healthz", get(|| async { "OK" }))
-        .route("/readyz", get(|| async { "OK" }))
+    // Only the check endpoints require a signature, health probes stay open
+    let checks = Router::new()
         .route(
             "/v1alpha1/tcpmonitor",
             post(tcp_monitor::v1alpha1::TCPMonitor::handle_http),
         )
+        .route(
+            "/v1alpha1/tcpmonitor/check",
+            post(worker::check_inline::<tcp_monitor::v1alpha1::TCPMonitor>),
+        )
+        .route(
+            "/v1alpha1/tcpmonitor/probe",
+            post(worker::probe_inline::<tcp_monitor::v1alpha1::TCPMonitor>),
+        )
         .route(
             "/v1alpha1/httpmonitor",
             post(http_monitor::v1alpha1::HTTPMonitor::handle_http),
         )
-        .with_state(state);
+        .route(
+            "/v1alpha1/httpmonitor/check",
+            post(worker::check_inline::<http_monitor::v1alpha1::HTTPMonitor>),
+        )
+        .route(
+            "/v1alpha1/httpmonitor/probe",
+            post(worker::probe_inline::<http_monitor::v1alpha1::HTTPMonitor>),
+        )
+        .route(
+            "/v1alpha2/httpmonitor",
+            post(http_monitor::v1alpha2::HTTPMonitor::handle_http),
+        )
+        .route(
+            "/v1alpha2/httpmonitor/check",
+            post(worker::check_inline::<http_monitor::v1alpha2::HTTPMonitor>),
+        )
+        .route(
+            "/v1alpha2/httpmonitor/probe",
+            post(worker::probe_inline::<http_monitor::v1alpha2::HTTPMonitor>),
+        )
+        .route(
+            "/v1alpha1/dnsmonitor",
+            post(dns_monitor::v1alpha1::DNSMonitor::handle_http),
+        )
+        .route(
+            "/v1alpha1/dnsmonitor/check",
+            post(worker::check_inline::<dns_monitor::v1alpha1::DNSMonitor>),
+        )
+        .route(
+            "/v1alpha1/dnsmonitor/probe",
+            post(worker::probe_inline::<dns_monitor::v1alpha1::DNSMonitor>),
+        )
+        .route(
+            "/v1alpha1/tlscertificatemonitor",
+            post(tls_certificate_monitor::v1alpha1::TLSCertificateMonitor::handle_http),
+        )
+        .route(
+            "/v1alpha1/tlscertificatemonitor/check",
+            post(worker::check_inline::<tls_certificate_monitor::v1alpha1::TLSCertificateMonitor>),
+        )
+        .route(
+            "/v1alpha1/tlscertificatemonitor/probe",
+            post(worker::probe_inline::<tls_certificate_monitor::v1alpha1::TLSCertificateMonitor>),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_signature,
+        ));
+
+    let mut app = Router::new()
+        .route("/healthz", get(|| async { "OK" }))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics::handle_metrics))
+        .merge(checks);
+
+    // /probe connects to whatever target it is given, so it is only served when modules are configured
+    if !state.modules.is_empty() {
+        info!("Serving /probe with modules {:?}", state.modules.keys());
+        let probe = Router::new()
+            .route("/probe", get(blackbox::handle_probe))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth::require_probe_token,
+            ));
+        app = app.merge(probe);
+    }
+    let app = app.with_state(state);
 
-    axum::serve(listener, app).await?;
+    match &settings.worker.tls {
+        Some(tls) => {
+            let acceptor = auth::build_server_tls(tls)?;
+            info!("Serving TLS on {}", local_addr);
+            axum_server::from_tcp(listener.into_std()?)
+                .acceptor(OpenSSLAcceptor::new(OpenSSLConfig::from_acceptor(acceptor)))
+                .serve(app.into_make_service())
+                .await?;
+        }
+        None => axum::serve(listener, app).await?,
+    }
 
     Ok(())
 }
+
+/// Reports not ready while the queue is full so no more checks are routed here
+async fn readyz(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    if state.queue.is_full() {
+        (StatusCode::SERVICE_UNAVAILABLE, "queue full")
+    } else {
+        (StatusCode::OK, "OK")
+    }
+}
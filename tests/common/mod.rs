@@ -1,8 +1,12 @@
 use kastlewatch::{controller, shared, worker};
 use kube::{Client, Config};
-use shared::resources::monitors::http_monitor::v1alpha1::HTTPMonitor;
+use shared::resources::monitors::dns_monitor::v1alpha1::DNSMonitor;
+use shared::resources::monitors::http_monitor;
 use shared::resources::monitors::tcp_monitor::v1alpha1::TCPMonitor;
+use shared::resources::monitors::tls_certificate_monitor::v1alpha1::TLSCertificateMonitor;
 use shared::resources::notifiers::discord_notifier::v1alpha1::DiscordNotifier;
+use shared::resources::notifiers::slack_notifier::v1alpha1::SlackNotifier;
+use shared::resources::notifiers::webhook_notifier::v1alpha1::WebhookNotifier;
 use std::sync::Mutex;
 use testcontainers::core::IntoContainerPort;
 use testcontainers::{ContainerAsync, ImageExt, runners::AsyncRunner};
@@ -76,8 +80,12 @@ async fn setup_k3s() -> anyhow::Result<(Client, Mutex<Option<ContainerAsync<K3s>
 
     // Init CRDs
     controller::crd_manager::init_crds::<TCPMonitor>(client.clone()).await?;
-    controller::crd_manager::init_crds::<HTTPMonitor>(client.clone()).await?;
+    controller::crd_manager::init_crd(client.clone(), http_monitor::crd()).await?;
+    controller::crd_manager::init_crds::<DNSMonitor>(client.clone()).await?;
+    controller::crd_manager::init_crds::<TLSCertificateMonitor>(client.clone()).await?;
     controller::crd_manager::init_crds::<DiscordNotifier>(client.clone()).await?;
+    controller::crd_manager::init_crds::<SlackNotifier>(client.clone()).await?;
+    controller::crd_manager::init_crds::<WebhookNotifier>(client.clone()).await?;
 
     Ok((client, Mutex::new(Some(node))))
 }
@@ -88,24 +96,30 @@ pub async fn start_services(client: Client) -> anyhow::Result<String> {
     let worker_port = listener.local_addr()?.port();
     let worker_base_url = format!("http://127.0.0.1:{}", worker_port);
 
+    let settings = shared::settings::Settings {
+        controller: shared::settings::ControllerSettings {
+            base_url: worker_base_url.clone(),
+            ..Default::default()
+        },
+        worker: shared::settings::WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
     let worker_client = client.clone();
+    let worker_settings = settings.clone();
     tokio::spawn(async move {
-        if let Err(e) = worker::server::run(worker_client, listener).await {
+        if let Err(e) = worker::server::run(Some(worker_client), listener, worker_settings).await {
             eprintln!("Worker failed: {:?}", e);
         }
     });
 
     // 2. Start Controller
     let controller_client = client.clone();
-    let controller_settings = shared::settings::Settings {
-        controller: shared::settings::ControllerSettings {
-            base_url: worker_base_url.clone()
-        },
-        worker: shared::settings::WorkerSettings {
-            host: "0.0.0.0".to_string(),
-            port: 3000,
-        },
-    };
+    let controller_settings = settings;
 
     tokio::spawn(async move {
         if let Err(e) = controller::controller::run(controller_client, controller_settings).await {
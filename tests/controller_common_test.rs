@@ -1,12 +1,18 @@
 use http::{Request, Response};
 use kastlewatch::controller::common;
-use kastlewatch::shared::context::Context;
-use kastlewatch::shared::resources::common::MonitorConfigSpec;
+use kastlewatch::shared::context::{Context, WorkerDispatch};
+use kastlewatch::shared::resources::common::{
+    FlapDetectionSpec, MonitorConfigSpec, SecretKeySelector,
+};
+use kastlewatch::shared::resources::monitors::http_monitor::BearerToken;
 use kastlewatch::shared::resources::monitors::http_monitor::v1alpha1::{
     HTTPMonitor, HTTPMonitorSpec, Method,
 };
 use kastlewatch::shared::resources::monitors::tcp_monitor::v1alpha1::{TCPMonitor, TCPMonitorSpec};
-use kastlewatch::shared::settings::{ControllerSettings, Settings, WorkerSettings};
+use kastlewatch::shared::settings::{
+    ControllerSettings, LocationSettings, Settings, WorkerServiceSettings, WorkerSettings,
+};
+use kastlewatch::shared::sharding::WorkerShards;
 use kube::Client;
 use kube::client::Body;
 use kube::runtime::controller::Action;
@@ -21,16 +27,31 @@ async fn test_tcp_reconcile_success() {
     let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
     let client = Client::new(mock_service, "default");
 
+    let worker = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .and(wiremock::matchers::path("/v1alpha1/tcpmonitor"))
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&worker)
+        .await;
+
     let settings = Settings {
         controller: ControllerSettings {
-            base_url: "http://worker:3000".to_string(),
+            base_url: worker.uri(),
+            ..Default::default()
         },
         worker: WorkerSettings {
             host: "0.0.0.0".to_string(),
             port: 3000,
+            ..Default::default()
         },
+        ..Default::default()
     };
-    let ctx = Arc::new(Context { client, settings });
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
 
     let monitor = TCPMonitor::new(
         "test-monitor",
@@ -42,7 +63,9 @@ async fn test_tcp_reconcile_success() {
                 retries: 3,
                 polling_frequency: 10,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
 
@@ -58,16 +81,31 @@ async fn test_http_reconcile_success() {
     let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
     let client = Client::new(mock_service, "default");
 
+    let worker = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .and(wiremock::matchers::path("/v1alpha1/httpmonitor"))
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&worker)
+        .await;
+
     let settings = Settings {
         controller: ControllerSettings {
-            base_url: "http://worker:3000".to_string(),
+            base_url: worker.uri(),
+            ..Default::default()
         },
         worker: WorkerSettings {
             host: "0.0.0.0".to_string(),
             port: 3000,
+            ..Default::default()
         },
+        ..Default::default()
     };
-    let ctx = Arc::new(Context { client, settings });
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
 
     let monitor = HTTPMonitor::new(
         "test-monitor",
@@ -81,7 +119,9 @@ async fn test_http_reconcile_success() {
                 retries: 3,
                 polling_frequency: 10,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
 
@@ -100,13 +140,20 @@ async fn test_http_reconcile_invalid_base64() {
     let settings = Settings {
         controller: ControllerSettings {
             base_url: "http://worker:3000".to_string(),
+            ..Default::default()
         },
         worker: WorkerSettings {
             host: "0.0.0.0".to_string(),
             port: 3000,
+            ..Default::default()
         },
+        ..Default::default()
     };
-    let ctx = Arc::new(Context { client, settings });
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
 
     let monitor = HTTPMonitor::new(
         "test-monitor",
@@ -120,7 +167,9 @@ async fn test_http_reconcile_invalid_base64() {
                 retries: 3,
                 polling_frequency: 10,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
 
@@ -129,6 +178,61 @@ async fn test_http_reconcile_invalid_base64() {
     assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_reconcile_probe_only_rejects_secrets() {
+    let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            base_url: "http://worker:3000".to_string(),
+            probe_only: Some(true),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            probe_only: Some(true),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
+
+    let monitor = HTTPMonitor::new(
+        "test-monitor",
+        HTTPMonitorSpec {
+            url: "http://example.com".to_string(),
+            method: Method::GET,
+            status_code: None,
+            base64_data: None,
+            bearer_token: Some(BearerToken {
+                token_secret_ref: SecretKeySelector {
+                    name: "api-token".to_string(),
+                    key: "token".to_string(),
+                },
+            }),
+            monitor_config: MonitorConfigSpec {
+                timeout: 5,
+                retries: 3,
+                polling_frequency: 10,
+                notifiers_match_labels: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    // The worker has no credentials to read the token with, so nothing is dispatched
+    let result = common::reconcile(Arc::new(monitor), ctx).await;
+
+    assert!(matches!(result, Err(common::Error::Anyhow(_))));
+}
+
 #[tokio::test]
 async fn test_reconcile_skips_recent_check() {
     let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
@@ -137,13 +241,20 @@ async fn test_reconcile_skips_recent_check() {
     let settings = Settings {
         controller: ControllerSettings {
             base_url: "http://worker:3000".to_string(),
+            ..Default::default()
         },
         worker: WorkerSettings {
             host: "0.0.0.0".to_string(),
             port: 3000,
+            ..Default::default()
         },
+        ..Default::default()
     };
-    let ctx = Arc::new(Context { client, settings });
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
 
     let mut monitor = TCPMonitor::new(
         "test-monitor",
@@ -155,7 +266,9 @@ async fn test_reconcile_skips_recent_check() {
                 retries: 3,
                 polling_frequency: 30,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
 
@@ -164,6 +277,7 @@ async fn test_reconcile_skips_recent_check() {
     monitor.status = Some(MonitorStatus {
         last_checked: Some(last_checked.to_rfc3339()),
         state: MonitorState::Healthy,
+        ..Default::default()
     });
 
     let result = common::reconcile(Arc::new(monitor), ctx).await;
@@ -178,3 +292,315 @@ async fn test_reconcile_skips_recent_check() {
 
     assert!(action == expected_29 || action == expected_28, "Expected requeue of 28s or 29s, got {:?}", action);
 }
+
+#[tokio::test]
+async fn test_reconcile_backs_off_when_worker_busy() {
+    let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    let worker = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .and(wiremock::matchers::path("/v1alpha1/tcpmonitor"))
+        .respond_with(wiremock::ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+        .mount(&worker)
+        .await;
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            base_url: worker.uri(),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
+
+    let monitor = TCPMonitor::new(
+        "test-monitor",
+        TCPMonitorSpec {
+            host: "localhost".to_string(),
+            port: 8080,
+            monitor_config: MonitorConfigSpec {
+                timeout: 5,
+                retries: 3,
+                polling_frequency: 60,
+                notifiers_match_labels: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let result = common::reconcile(Arc::new(monitor), ctx).await;
+
+    assert_eq!(result.unwrap(), Action::requeue(Duration::from_secs(7)));
+}
+
+#[tokio::test]
+async fn test_reconcile_worker_unreachable() {
+    let (mock_service, mut handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    // Record the requests the controller makes to the API server
+    let api_server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        while let Some((request, send)) = handle.next_request().await {
+            let method = request.method().to_string();
+            let uri = request.uri().to_string();
+            let body = request.into_body().collect_bytes().await.unwrap();
+            requests.push((method, uri, String::from_utf8_lossy(&body).to_string()));
+            send.send_response(
+                Response::builder()
+                    .status(500)
+                    .body(Body::from(b"{}".to_vec()))
+                    .unwrap(),
+            );
+        }
+        requests
+    });
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            // Nothing listens on port 9
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
+
+    let monitor = Arc::new(TCPMonitor::new(
+        "test-monitor",
+        TCPMonitorSpec {
+            host: "localhost".to_string(),
+            port: 8080,
+            monitor_config: MonitorConfigSpec {
+                timeout: 5,
+                retries: 3,
+                polling_frequency: 60,
+                notifiers_match_labels: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+
+    let result = common::reconcile(monitor.clone(), ctx.clone()).await;
+    assert!(matches!(result, Err(common::Error::Dispatch(_))));
+
+    // Consecutive failures back off exponentially
+    let error = result.unwrap_err();
+    assert_eq!(
+        common::error_policy(monitor.clone(), &error, ctx.clone()),
+        Action::requeue(Duration::from_secs(5))
+    );
+
+    // Reconciling again during the backoff, as the status patch does, does not dispatch
+    let result = common::reconcile(monitor.clone(), ctx.clone()).await;
+    assert!(result.is_ok());
+
+    assert_eq!(
+        common::error_policy(monitor.clone(), &error, ctx.clone()),
+        Action::requeue(Duration::from_secs(10))
+    );
+
+    // A deleted monitor's failure count is dropped, so a recreated one starts over
+    common::forget_monitor::<TCPMonitor>(&ctx, "", "test-monitor");
+    assert!(ctx.dispatch.failures.lock().unwrap().is_empty());
+    assert_eq!(
+        common::error_policy(monitor.clone(), &error, ctx.clone()),
+        Action::requeue(Duration::from_secs(5))
+    );
+
+    drop(ctx);
+    let requests = api_server.await.unwrap();
+    let (method, uri, body) = &requests[0];
+    assert_eq!(method, "PATCH");
+    assert!(uri.contains("/tcpmonitors/test-monitor/status"));
+    assert!(body.contains("WorkerUnreachable"));
+    let (method, uri, _) = &requests[1];
+    assert_eq!(method, "POST");
+    assert!(uri.contains("/events"));
+    assert_eq!(requests.len(), 2);
+}
+
+#[tokio::test]
+async fn test_reconcile_unknown_location() {
+    let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            base_url: "http://worker:3000".to_string(),
+            locations: Some(vec![LocationSettings {
+                name: "eu".to_string(),
+                base_url: "http://worker-eu:3000".to_string(),
+            }]),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
+
+    let monitor = TCPMonitor::new(
+        "test-monitor",
+        TCPMonitorSpec {
+            host: "localhost".to_string(),
+            port: 8080,
+            monitor_config: MonitorConfigSpec {
+                timeout: 5,
+                retries: 3,
+                polling_frequency: 60,
+                notifiers_match_labels: None,
+                locations: Some(vec!["us".to_string()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let result = common::reconcile(Arc::new(monitor.clone()), ctx.clone()).await;
+
+    assert!(matches!(result, Err(common::Error::Anyhow(_))));
+
+    // A quorum the selected locations can never reach
+    let mut monitor = monitor;
+    monitor.spec.monitor_config.locations = Some(vec!["eu".to_string()]);
+    monitor.spec.monitor_config.location_quorum = Some(2);
+    let result = common::reconcile(Arc::new(monitor), ctx).await;
+
+    assert!(matches!(result, Err(common::Error::Anyhow(_))));
+}
+
+#[tokio::test]
+async fn test_reconcile_invalid_flap_detection() {
+    let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            base_url: "http://worker:3000".to_string(),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: Default::default(),
+    });
+
+    let monitor = |max_transitions, window| {
+        TCPMonitor::new(
+            "test-monitor",
+            TCPMonitorSpec {
+                host: "localhost".to_string(),
+                port: 8080,
+                monitor_config: MonitorConfigSpec {
+                    timeout: 5,
+                    retries: 3,
+                    polling_frequency: 60,
+                    notifiers_match_labels: None,
+                    flap_detection: Some(FlapDetectionSpec {
+                        max_transitions,
+                        window,
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+    };
+
+    let result = common::reconcile(Arc::new(monitor(0, 600)), ctx.clone()).await;
+    assert!(matches!(result, Err(common::Error::Anyhow(_))));
+
+    let result = common::reconcile(Arc::new(monitor(5, 0)), ctx).await;
+    assert!(matches!(result, Err(common::Error::Anyhow(_))));
+}
+
+#[tokio::test]
+async fn test_reconcile_waits_for_worker_replicas() {
+    let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = Client::new(mock_service, "default");
+
+    let settings = Settings {
+        controller: ControllerSettings {
+            base_url: "http://worker:3000".to_string(),
+            worker_service: Some(WorkerServiceSettings {
+                namespace: "kastlewatch".to_string(),
+                name: "worker".to_string(),
+                port: None,
+                scheme: None,
+            }),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let shards = Arc::new(WorkerShards::default());
+    let ctx = Arc::new(Context {
+        client,
+        settings,
+        dispatch: WorkerDispatch {
+            shards: Some(shards.clone()),
+            ..Default::default()
+        },
+    });
+
+    let monitor = TCPMonitor::new(
+        "test-monitor",
+        TCPMonitorSpec {
+            host: "localhost".to_string(),
+            port: 8080,
+            monitor_config: MonitorConfigSpec {
+                timeout: 5,
+                retries: 3,
+                polling_frequency: 60,
+                notifiers_match_labels: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    // Before the replicas are listed the monitor waits, without marking the worker unreachable
+    let result = common::reconcile(Arc::new(monitor), ctx).await;
+    assert_eq!(result.unwrap(), Action::requeue(Duration::from_secs(2)));
+    assert!(!shards.is_synced());
+}
@@ -49,6 +49,7 @@ async fn test_discord_notification_flow() -> anyhow::Result<()> {
             key: "url".to_string(),
         },
         message_format: None,
+        format: None,
     });
     // Add labels for matching
     let mut notifier = notifier;
@@ -77,7 +78,9 @@ async fn test_discord_notification_flow() -> anyhow::Result<()> {
                     "type".to_string(),
                     "discord".to_string(),
                 )])),
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
     monitors.create(&PostParams::default(), &monitor).await?;
@@ -41,10 +41,12 @@ async fn test_http_monitor_flow() -> anyhow::Result<()> {
                 timeout: 5,
                 retries: 3,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
             method: Method::GET,
             status_code: None,
             base64_data: None,
+            ..Default::default()
         },
     );
 
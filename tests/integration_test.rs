@@ -29,7 +29,9 @@ async fn test_end_to_end_controller_worker_flow() -> anyhow::Result<()> {
                 timeout: 5,
                 retries: 3,
                 notifiers_match_labels: None,
+                ..Default::default()
             },
+            ..Default::default()
         },
     );
 
@@ -0,0 +1,293 @@
+use http::{Request, Response};
+use kastlewatch::shared::resources::common::{MonitorConfigSpec, MonitorState};
+use kastlewatch::shared::resources::monitors::tcp_monitor::v1alpha1::{TCPMonitor, TCPMonitorSpec};
+use kastlewatch::shared::resources::worker::ProbeResult;
+use kastlewatch::shared::settings::{
+    ControllerSettings, ProbeAccessSettings, ProbeModuleSettings, Prober, Settings, WorkerSettings,
+};
+use kastlewatch::worker;
+use kube::Client;
+use kube::client::Body;
+use std::collections::BTreeMap;
+use tokio::net::TcpListener;
+use tower_test::mock;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn start_worker(probe_only: bool) -> String {
+    start_worker_with(probe_only, |_| ()).await
+}
+
+async fn start_worker_with(
+    probe_only: bool,
+    configure: impl FnOnce(&mut WorkerSettings),
+) -> String {
+    let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+    let client = (!probe_only).then(|| Client::new(mock_service, "default"));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let mut settings = Settings {
+        controller: ControllerSettings {
+            base_url: base_url.clone(),
+            ..Default::default()
+        },
+        worker: WorkerSettings {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            probe_only: Some(probe_only),
+            modules: Some(BTreeMap::from([(
+                "tcp_connect".to_string(),
+                ProbeModuleSettings {
+                    prober: Some(Prober::Tcp),
+                    timeout: Some(2),
+                    spec: None,
+                    monitor: None,
+                },
+            )])),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    configure(&mut settings.worker);
+
+    tokio::spawn(async move {
+        // Keep the kube mock alive for as long as the worker runs
+        let _handle = handle;
+        worker::server::run(client, listener, settings)
+            .await
+            .unwrap();
+    });
+    base_url
+}
+
+fn tcp_monitor(port: u16) -> TCPMonitor {
+    TCPMonitor::new(
+        "dry-run-monitor",
+        TCPMonitorSpec {
+            host: "127.0.0.1".to_string(),
+            port,
+            monitor_config: MonitorConfigSpec {
+                timeout: 2,
+                retries: 0,
+                polling_frequency: 60,
+                notifiers_match_labels: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_dry_run_check_returns_result() {
+    let base_url = start_worker(false).await;
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = target.local_addr().unwrap().port();
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/v1alpha1/tcpmonitor/check?dryRun=true",
+            base_url
+        ))
+        .json(&tcp_monitor(port))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["kind"], "TCPMonitor");
+    assert_eq!(body["name"], "dry-run-monitor");
+    assert_eq!(body["target"], format!("127.0.0.1:{}", port));
+    let state: MonitorState = serde_json::from_value(body["result"]["state"].clone()).unwrap();
+    assert_eq!(state, MonitorState::Healthy);
+}
+
+#[tokio::test]
+async fn test_check_requires_dry_run() {
+    let base_url = start_worker(false).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1alpha1/tcpmonitor/check", base_url))
+        .json(&tcp_monitor(1))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_probe_only_worker_returns_result() {
+    let base_url = start_worker(true).await;
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = target.local_addr().unwrap().port();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/v1alpha1/tcpmonitor/probe", base_url))
+        .json(&tcp_monitor(port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let probe_result: ProbeResult = response.json().await.unwrap();
+    assert_eq!(probe_result.result.state, MonitorState::Healthy);
+    assert_eq!(probe_result.attempts, 1);
+
+    // Without Kubernetes credentials the worker cannot record results itself
+    let response = client
+        .post(format!("{}/v1alpha1/tcpmonitor", base_url))
+        .json(&tcp_monitor(port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 501);
+}
+
+#[tokio::test]
+async fn test_blackbox_probe() {
+    let base_url = start_worker(true).await;
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = target.local_addr().unwrap().port();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!(
+            "{}/probe?module=tcp_connect&target=127.0.0.1:{}",
+            base_url, port
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("probe_success 1\n"), "{}", body);
+    assert!(body.contains("probe_duration_seconds "));
+
+    drop(target);
+    let body = client
+        .get(format!(
+            "{}/probe?module=tcp_connect&target=127.0.0.1:{}",
+            base_url, port
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("probe_success 0\n"), "{}", body);
+
+    let response = client
+        .get(format!("{}/probe?module=icmp&target=127.0.0.1", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_blackbox_probe_access() {
+    let token_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(token_file.path(), "probe-token\n").unwrap();
+    let token_path = token_file.path().to_str().unwrap().to_string();
+    let base_url = start_worker_with(true, |worker| {
+        worker.probe_access = Some(ProbeAccessSettings {
+            bearer_token_file: Some(token_path),
+            allowed_targets: Some(vec!["127.0.0.1/32".to_string()]),
+        });
+    })
+    .await;
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = target.local_addr().unwrap().port();
+    let client = reqwest::Client::new();
+    let probe = |host: &str| {
+        format!(
+            "{}/probe?module=tcp_connect&target={}:{}",
+            base_url, host, port
+        )
+    };
+
+    let response = client.get(probe("127.0.0.1")).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .get(probe("127.0.0.1"))
+        .bearer_auth("probe-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(probe("127.0.0.2"))
+        .bearer_auth("probe-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn test_blackbox_probe_redirects() {
+    let target = MockServer::start().await;
+    let port = target.address().port();
+    let redirect =
+        |location: String| ResponseTemplate::new(302).insert_header("Location", location);
+    Mock::given(path("/allowed"))
+        .respond_with(redirect(format!("http://127.0.0.1:{}/health", port)))
+        .mount(&target)
+        .await;
+    Mock::given(path("/denied"))
+        .respond_with(redirect(format!("http://localhost:{}/health", port)))
+        .mount(&target)
+        .await;
+    Mock::given(path("/health"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&target)
+        .await;
+
+    let base_url = start_worker_with(true, |worker| {
+        worker.probe_access = Some(ProbeAccessSettings {
+            bearer_token_file: None,
+            allowed_targets: Some(vec!["127.0.0.1/32".to_string()]),
+        });
+        worker.modules.get_or_insert_default().insert(
+            "http_2xx".to_string(),
+            ProbeModuleSettings {
+                prober: Some(Prober::Http),
+                timeout: Some(2),
+                spec: None,
+                monitor: None,
+            },
+        );
+    })
+    .await;
+    let probe = |path: &str| {
+        let url = format!(
+            "{}/probe?module=http_2xx&target={}{}",
+            base_url,
+            target.uri(),
+            path
+        );
+        async move { reqwest::get(url).await.unwrap().text().await.unwrap() }
+    };
+
+    assert!(probe("/allowed").await.contains("probe_success 1\n"));
+    assert!(probe("/denied").await.contains("probe_success 0\n"));
+}
+
+#[tokio::test]
+async fn test_blackbox_probe_needs_modules() {
+    let base_url = start_worker_with(true, |worker| worker.modules = None).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/probe?target=127.0.0.1:80", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}